@@ -1,11 +1,25 @@
 use std::sync::{ Arc };
 use std::net::SocketAddr;
+use std::time::Duration;
 
 use clap::{ Command, arg, Parser };
 
 use KRdmaKit::{ MemoryRegion, QueuePair, QueuePairBuilder, QueuePairStatus, UDriver, DatapathError };
 use KRdmaKit::services_user::MRInfo;
 
+use netbencher_core::{
+    Arrival,
+    BenchReporter,
+    CoordinatedReporterMaster,
+    CorePlacement,
+    FileReporter,
+    PrometheusExporter,
+    PrometheusReporter,
+    SimpleBenchReporter,
+    StatFile,
+    ThresholdReporter,
+};
+
 use rand::RngCore;
 use rand_chacha::ChaCha8Rng;
 
@@ -28,10 +42,26 @@ pub struct CmdlineArgs {
     #[arg(long, default_value_t = 10240)]
     pub random_space: u64,
 
-    /// The life of the bench (seconds)
+    /// The life of the bench (seconds), i.e., the measured window
     #[arg(long, default_value_t = 15)]
     pub life: u32,
 
+    /// Seconds to warm up before measuring, whose results are excluded
+    #[arg(long, default_value_t = 0)]
+    pub warmup: u32,
+
+    /// Seconds to keep running after measuring, whose results are excluded
+    #[arg(long, default_value_t = 0)]
+    pub cooldown: u32,
+
+    /// The interval of the reports (milliseconds)
+    #[arg(long, default_value_t = 1000)]
+    pub report_interval: u64,
+
+    /// How old (milliseconds) a report can be to be aggregated at the master, 1.5x of the interval by default
+    #[arg(long)]
+    pub staleness: Option<u64>,
+
     /// The listening address of server
     #[arg(long, default_value_t = String::from("127.0.0.1:8888"))]
     pub listen_addr: String,
@@ -39,6 +69,36 @@ pub struct CmdlineArgs {
     /// The reporting address of server
     #[arg(long, default_value_t = String::from("127.0.0.1:10001"))]
     pub report_addr: String,
+
+    /// Write the reports to this file, as CSV if it ends with `.csv`, otherwise as JSON lines
+    #[arg(long)]
+    pub output: Option<String>,
+
+    /// Serve the reports on `http://<metrics_addr>/metrics` for Prometheus
+    #[arg(long)]
+    pub metrics_addr: Option<String>,
+
+    /// Warn about the intervals whose throughput deviates from the running mean by more than this ratio (e.g., 0.1)
+    #[arg(long)]
+    pub flag_deviation: Option<f64>,
+
+    /// Report the throughput of each worker, and warn if their spread, i.e., (max - min) / mean, exceeds this ratio (e.g., 0.5).
+    /// At the master, warn if the throughputs of the clients (or of the workers of a client) spread more than it
+    #[arg(long)]
+    pub worker_spread: Option<f64>,
+
+    /// Pin the worker threads: `compact`, `scatter`, `node:<n>` (a NUMA node), `nic:<n>` (the NUMA node of the n-th RDMA NIC),
+    /// `nic:<device>` (the NUMA node of an RDMA NIC by name, e.g., `nic:mlx5_1`), or a core list, e.g., `0,2,4-7`
+    #[arg(long)]
+    pub pin: Option<CorePlacement>,
+
+    /// End the measured window once the clients finished this many ops in total of all the threads, at most <life> seconds
+    #[arg(long, conflicts_with = "max_bytes")]
+    pub max_ops: Option<u64>,
+
+    /// End the measured window once the clients transferred this many bytes in total of all the threads, at most <life> seconds
+    #[arg(long)]
+    pub max_bytes: Option<u64>,
     
     /* Client-specific fields */
     /// Number of threads used
@@ -77,6 +137,15 @@ pub struct CmdlineArgs {
     #[arg(long)]
     pub latency_test: bool,
 
+    /// Run open-loop at this rate (ops/s) in total of all the threads, instead of waiting for each batch,
+    /// the latency is measured from the intended send time of each op
+    #[arg(long, value_parser = parse_rate)]
+    pub rate: Option<f64>,
+
+    /// The inter-arrival times of the open-loop ops: poisson or constant
+    #[arg(long, default_value = "poisson")]
+    pub arrival: Arrival,
+
     /// One signal in <signal_size> requests
     #[arg(long, default_value_t = 16)]
     pub signal_size: usize,
@@ -89,6 +158,14 @@ pub struct CmdlineArgs {
     #[arg(long)]
     pub report: bool,
 
+    /// Whether the master at <report_addr> starts and stops the run (both client and server)
+    #[arg(long, requires = "report")]
+    pub controlled: bool,
+
+    /// Number of clients the server waits for before starting a controlled run
+    #[arg(long, default_value_t = 1)]
+    pub num_clients: usize,
+
     /// Whether to generate a signal at client for every request
     #[arg(long)]
     pub signaled: bool,
@@ -97,7 +174,7 @@ pub struct CmdlineArgs {
     #[arg(long)]
     pub doorbell: bool,
 
-    /// Whether to profile the posting cost
+    /// Whether to profile the posting cost, the latency of each post is logged per thread at the end of the run
     #[arg(long)]
     pub profile: bool,
     /* Server-specific fields */
@@ -116,11 +193,23 @@ impl Clone for CmdlineArgs {
         Self {
             listen_addr: self.listen_addr.clone(),
             report_addr: self.report_addr.clone(),
+            output: self.output.clone(),
+            metrics_addr: self.metrics_addr.clone(),
+            pin: self.pin.clone(),
             ..*self
         }
     }
 }
 
+/// Parse the open-loop rate, which must be a positive number of ops per second
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if !(rate.is_finite() && rate > 0.0) {
+        return Err(format!("the rate must be positive, got {}", s));
+    }
+    Ok(rate)
+}
+
 impl CmdlineArgs {
     /// coordinate the arguments to make them be compatible to each other
     pub fn coordinate(&mut self) {
@@ -130,6 +219,50 @@ impl CmdlineArgs {
         self.random_space = std::cmp::max(self.threads * self.thread_gap, self.random_space);
     }
 
+    /// Build the reporter of the client from the reporting arguments:
+    /// the per-worker breakdown, the output file, the Prometheus metrics and the deviation warnings, if requested
+    pub fn build_reporter(&self) -> Box<dyn BenchReporter> {
+        let mut inner_reporter = SimpleBenchReporter::new_with_id(self.client_id.try_into().unwrap());
+        if let Some(max_spread) = self.worker_spread {
+            inner_reporter = inner_reporter.with_worker_breakdown(max_spread);
+        }
+        let mut reporter: Box<dyn BenchReporter> = match &self.output {
+            Some(path) => Box::new(
+                FileReporter::new(path, inner_reporter).expect("failed to create the output file")
+            ),
+            None => Box::new(inner_reporter),
+        };
+        if let Some(addr) = &self.metrics_addr {
+            let exporter = PrometheusExporter::bind(addr.parse().unwrap())
+                .expect("failed to serve the metrics");
+            reporter = Box::new(PrometheusReporter::new(exporter, reporter));
+        }
+        if let Some(max_deviation) = self.flag_deviation {
+            reporter = Box::new(ThresholdReporter::new(max_deviation, reporter));
+        }
+        reporter
+    }
+
+    /// Configure the master of the server from the reporting arguments:
+    /// the output file, the staleness, the spread warnings and the Prometheus metrics, if requested
+    pub fn configure_master(&self, master: &mut CoordinatedReporterMaster) {
+        if let Some(path) = &self.output {
+            master.set_output(StatFile::create(path).expect("failed to create the output file"));
+        }
+        if let Some(staleness) = self.staleness {
+            master.set_staleness(Duration::from_millis(staleness));
+        }
+        if let Some(max_spread) = self.worker_spread {
+            master.set_max_spread(max_spread);
+        }
+        if let Some(addr) = &self.metrics_addr {
+            master.set_exporter(
+                PrometheusExporter::bind(addr.parse().unwrap())
+                    .expect("failed to serve the metrics")
+            );
+        }
+    }
+
     pub fn create_rc(&self, thread_id: usize) -> Result<(Arc<QueuePair>, Arc<MemoryRegion>, MRInfo), ()> {
         let addr: SocketAddr = self.listen_addr.parse().unwrap();
         let client_port: u8 = 1;
@@ -2,6 +2,16 @@ use std::sync::{ Arc };
 
 use clap::{ Command, arg, Arg, ArgAction, Parser };
 
+use netbencher_core::{
+    BenchReporter,
+    CorePlacement,
+    FileReporter,
+    PrometheusExporter,
+    PrometheusReporter,
+    SimpleBenchReporter,
+    ThresholdReporter,
+};
+
 use rand::RngCore;
 use rand_chacha::ChaCha8Rng;
 
@@ -20,10 +30,22 @@ pub struct CmdlineArgs {
     #[arg(long, default_value_t = 10 * 1024)]
     pub random_space: u64,
 
-    /// The life of the bench (seconds)
+    /// The life of the bench (seconds), i.e., the measured window
     #[arg(long, default_value_t = 15)]
     pub life: u32,
 
+    /// Seconds to warm up before measuring, whose results are excluded
+    #[arg(long, default_value_t = 0)]
+    pub warmup: u32,
+
+    /// Seconds to keep running after measuring, whose results are excluded
+    #[arg(long, default_value_t = 0)]
+    pub cooldown: u32,
+
+    /// The interval of the reports (milliseconds)
+    #[arg(long, default_value_t = 1000)]
+    pub report_interval: u64,
+
     /// The listening address of server
     #[arg(long)]
     pub listen_addr: String,
@@ -31,6 +53,36 @@ pub struct CmdlineArgs {
     /// Whether to allcate memory regions using huge pages
     #[arg(long)]
     pub huge_page: bool,
+
+    /// Write the reports to this file, as CSV if it ends with `.csv`, otherwise as JSON lines
+    #[arg(long)]
+    pub output: Option<String>,
+
+    /// Serve the reports on `http://<metrics_addr>/metrics` for Prometheus
+    #[arg(long)]
+    pub metrics_addr: Option<String>,
+
+    /// Warn about the intervals whose throughput deviates from the running mean by more than this ratio (e.g., 0.1)
+    #[arg(long)]
+    pub flag_deviation: Option<f64>,
+
+    /// Report the throughput of each worker, and warn if their spread, i.e., (max - min) / mean, exceeds this ratio (e.g., 0.5).
+    /// At the master, warn if the throughputs of the clients (or of the workers of a client) spread more than it
+    #[arg(long)]
+    pub worker_spread: Option<f64>,
+
+    /// Pin the worker threads: `compact`, `scatter`, `node:<n>` (a NUMA node), `nic:<n>` (the NUMA node of the n-th RDMA NIC),
+    /// `nic:<device>` (the NUMA node of an RDMA NIC by name, e.g., `nic:mlx5_1`), or a core list, e.g., `0,2,4-7`
+    #[arg(long)]
+    pub pin: Option<CorePlacement>,
+
+    /// End the measured window once the clients finished this many ops in total of all the threads, at most <life> seconds
+    #[arg(long, conflicts_with = "max_bytes")]
+    pub max_ops: Option<u64>,
+
+    /// End the measured window once the clients transferred this many bytes in total of all the threads, at most <life> seconds
+    #[arg(long)]
+    pub max_bytes: Option<u64>,
     
     /* Client-specific fields */
     /// Client id, which will be used to generate unique seed
@@ -80,6 +132,9 @@ impl Clone for CmdlineArgs {
         Self {
             pci_dev: self.pci_dev.clone(),
             listen_addr: self.listen_addr.clone(),
+            output: self.output.clone(),
+            metrics_addr: self.metrics_addr.clone(),
+            pin: self.pin.clone(),
             ..*self
         }
     }
@@ -94,6 +149,30 @@ impl CmdlineArgs {
         self.random_space = std::cmp::max(self.threads * self.thread_gap, self.random_space);
     }
 
+    /// Build the reporter of the client from the reporting arguments:
+    /// the per-worker breakdown, the output file, the Prometheus metrics and the deviation warnings, if requested
+    pub fn build_reporter(&self) -> Box<dyn BenchReporter> {
+        let mut inner_reporter = SimpleBenchReporter::new_with_id(self.client_id.try_into().unwrap());
+        if let Some(max_spread) = self.worker_spread {
+            inner_reporter = inner_reporter.with_worker_breakdown(max_spread);
+        }
+        let mut reporter: Box<dyn BenchReporter> = match &self.output {
+            Some(path) => Box::new(
+                FileReporter::new(path, inner_reporter).expect("failed to create the output file")
+            ),
+            None => Box::new(inner_reporter),
+        };
+        if let Some(addr) = &self.metrics_addr {
+            let exporter = PrometheusExporter::bind(addr.parse().unwrap())
+                .expect("failed to serve the metrics");
+            reporter = Box::new(PrometheusReporter::new(exporter, reporter));
+        }
+        if let Some(max_deviation) = self.flag_deviation {
+            reporter = Box::new(ThresholdReporter::new(max_deviation, reporter));
+        }
+        reporter
+    }
+
     /// get next index to access in the random region
     pub fn get_next_index(&self, thread_idx: usize, rand: &mut ChaCha8Rng) -> u64 {
         let mut r = rand.next_u64();
@@ -23,8 +23,6 @@
 //!     ) -> (Vec<Arc<QueuePair>>, Vec<UdMeta>)
 //!     bootstrap UD server, after calling, server should be ready for client to send
 //! 
-//! Mod rdtsc
-//!     An x86-specific timer lib, should be banned with --features "ARM" in a ARM environment.
 //! Mod doca
 //!     CmdlineArgs: parse command line arguments for doca_related bench
 
@@ -36,13 +34,9 @@ pub mod ud_endpoint;
 pub mod ud_manager;
 pub mod ud_message;
 
-#[cfg(not(feature = "ARM"))]
-pub mod rdtsc;
-
 pub mod doca;
 
 pub const MIN_SERVER_LIFE: u32 = 30;
-pub const MAX_CLIENTS: usize = 24;
 
 /// maxium size of recv-batch posted
 pub const MAX_RECV_NUM: usize = 64;
@@ -62,7 +62,7 @@ fn post_dma_reqs<T>
 (
     thread_id: usize,
     runner: Arc<BenchRunner<T>>,
-    mut stat: Arc<BenchStat>,
+    mut stat: BenchStatWriter,
     args: CmdlineArgs,
     mut workq: Arc<DOCAWorkQueue<DMAEngine>>,
     local_buf: DOCABuffer,
@@ -91,8 +91,11 @@ fn post_dma_reqs<T>
     let mut rand = ChaCha8Rng::seed_from_u64(
         ((0xdeadbeaf + 73 * thread_id) as u64) + args.client_id * 37
     );
+    runner.setup_done();
+    let clock = Clock::get();
     while runner.running() {
         let mut start = 0;
+        let batch_start = clock.now();
         /* post dma requests */
         for i in 0..args.batch_size {
             let (src_offset, dst_offset) = match args.read {
@@ -120,6 +123,8 @@ fn post_dma_reqs<T>
                 };
                 match event {
                     Ok(_e) => {
+                        // the jobs of the batch are submitted together, so each one takes the time since the batch starts
+                        stat.record_latency_ns(clock.elapsed_ns(batch_start));
                         break;
                     }
                     Err(e) => {
@@ -133,16 +138,15 @@ fn post_dma_reqs<T>
             }
         }
 
-        unsafe {
-            Arc::get_mut_unchecked(&mut stat).finished_batch_ops(args.batch_size.try_into().unwrap());
-        }
+        stat.finished_batch_ops(args.batch_size.try_into().unwrap());
+        stat.transferred_bytes(args.batch_size as u64 * args.payload);
     }
 }
 
 pub fn perform_client_routine<T>(
     thread_id: usize,
     runner: Arc<BenchRunner<T>>,
-    stat: Arc<BenchStat>,
+    stat: BenchStatWriter,
     conn: Vec<u8>,
     mut args: CmdlineArgs
 )
@@ -219,5 +223,5 @@ pub fn perform_client_routine<T>(
         .to_buffer(&inv)
         .unwrap();
 
-    post_dma_reqs(thread_id, runner.clone(), stat.clone(), args, Arc::new(workq), local_dma_buf, remote_dma_buf);
+    post_dma_reqs(thread_id, runner.clone(), stat, args, Arc::new(workq), local_dma_buf, remote_dma_buf);
 }
\ No newline at end of file
@@ -8,17 +8,20 @@ mod connection;
 pub use connection::{ DocaConnInfo, DocaConnInfoMsg };
 pub use connection::DOCA_MAX_CONN_LENGTH;
 
-use std::{ thread, time };
+use std::time;
 use std::time::Duration;
 use tokio::runtime::Runtime;
 
 use bench_util::doca::args::*;
-use bench_util::{ MAX_CLIENTS, MIN_SERVER_LIFE };
+use bench_util::MIN_SERVER_LIFE;
 
 use netbencher_core::{
     CoordinatedReporterMaster,
     BenchRunner,
-    SimpleBenchReporter,
+    Shutdown,
+    Clock,
+    BenchPhases,
+    RunBound,
     CoordinatedReporter,
 };
 
@@ -27,30 +30,58 @@ use log::*;
 pub fn bootstrap_client(mut args: CmdlineArgs) {
     /* load config using TCP channel */
     let doca_conn_msg = Runtime::new().unwrap().block_on(recv_doca_config(args.listen_addr.parse().unwrap()));
-    let mut runner = BenchRunner::new(args.threads as usize);
+    let runner = BenchRunner::new_with_phases(args.threads as usize, BenchPhases::new(
+        Duration::from_secs(args.warmup.into()),
+        Duration::from_secs(args.life.into()),
+        Duration::from_secs(args.cooldown.into()),
+    ));
+    if let Some(placement) = &args.pin {
+        runner
+            .pin_workers(placement.clone())
+            .expect("failed to pin the workers");
+    }
+    if let Some(n) = args.max_ops {
+        runner.set_bound(RunBound::Ops(n));
+    } else if let Some(n) = args.max_bytes {
+        runner.set_bound(RunBound::Bytes(n));
+    }
+    // calibrate the clock once, before the workers time their ops
+    Clock::get();
+    let shutdown = Shutdown::install().expect("failed to install the Ctrl-C handler");
+    runner.stop_on(&shutdown);
     // let mut runner = BenchRunner::new(args.threads.try_into().unwrap());
     runner.run(move |thread_id, runner, stat, args| {
         perform_client_routine(thread_id, runner, stat, doca_conn_msg.clone(), args);
     }, args.clone());
 
-    let mut inner_reporter = SimpleBenchReporter::new_with_id(args.client_id.try_into().unwrap());
+    let mut inner_reporter = args.build_reporter();
 
-    for epoch in 0..args.life {
-        thread::sleep(time::Duration::from_secs(1));
+    for _phase in runner.phase_ticker(time::Duration::from_millis(args.report_interval)) {
         info!("{}", runner.report(&mut inner_reporter));
     }
-    runner.stop().unwrap();
+    if let Err(e) = runner.stop() {
+        error!("the run failed: {}", e);
+        std::process::exit(1);
+    }
+    info!("summary: {}", runner.summary());
 }
 
 pub fn bootstrap_server(mut args: CmdlineArgs) {
+    // the server outlives the warmup and cooldown of the clients
+    args.life += args.warmup + args.cooldown;
     if args.life < MIN_SERVER_LIFE {
         args.life = MIN_SERVER_LIFE;
     }
 
-    let mut runner = BenchRunner::new(1);
+    let runner = BenchRunner::new(1);
+    let shutdown = Shutdown::install().expect("failed to install the Ctrl-C handler");
+    runner.stop_on(&shutdown);
     runner.run(|thread_id, runner, stat, args| {
         perform_server_routine(runner, args);
     }, args.clone());
-    thread::sleep(Duration::from_secs(args.life.into()));
-    runner.stop().unwrap();
+    shutdown.sleep(Duration::from_secs(args.life.into()));
+    if let Err(e) = runner.stop() {
+        error!("the run failed: {}", e);
+        std::process::exit(1);
+    }
 }
\ No newline at end of file
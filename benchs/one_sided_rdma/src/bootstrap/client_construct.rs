@@ -1,4 +1,5 @@
 use std::sync::{ Arc };
+use std::collections::VecDeque;
 use bench_util::args::*;
 use bench_util::doorbell::RcDoorbellHelper;
 
@@ -14,7 +15,7 @@ use log::*;
 pub fn perform_client_routine<T>(
     thread_id: usize,
     runner: Arc<BenchRunner<T>>,
-    mut stat: Arc<BenchStat>,
+    mut stat: BenchStatWriter,
     args: CmdlineArgs
 )
     where T: Send + 'static + Sync + Copy
@@ -24,12 +25,17 @@ pub fn perform_client_routine<T>(
         Ok(res) => res,
         Err(()) => { panic!("Fail to bring up RC qp!") }
     };
+
+    runner.setup_done();
+    let clock = Clock::get();
     let mut rand = ChaCha8Rng::seed_from_u64(
         ((0xdeadbeaf + 73 * thread_id) as u64) + args.client_id * 37
     );
     let mut completions = [Default::default()];
 
     let mut pending: usize = 0;
+    // the time when the current signaled window starts
+    let mut window_start = clock.now();
     let start = 0;
 
     while runner.running() {
@@ -38,6 +44,9 @@ pub fn perform_client_routine<T>(
         for i in 0..args.factor {
             let index = args.get_next_index(thread_id, &mut rand);
             let signal = pending == 0;
+            if signal {
+                window_start = clock.now();
+            }
             if args.read {
                 qp.post_send_read(
                     &client_mr,
@@ -72,18 +81,20 @@ pub fn perform_client_routine<T>(
                     }
                 }
                 pending = 0;
+                // the unsignaled ops have no completion, so this is the latency of a window of `signal_size` ops
+                // (one sample per window), and the latency of each op only with --latency-test
+                stat.record_latency_ns(clock.elapsed_ns(window_start));
             }
         }
-        unsafe {
-            Arc::get_mut_unchecked(&mut stat).finished_batch_ops(args.factor);
-        }
+        stat.finished_batch_ops(args.factor);
+        stat.transferred_bytes(args.factor * args.payload);
     } // end of main benchmark loop
 }
 
 pub fn perform_client_doorbell_routine<T>(
     thread_id: usize,
     runner: Arc<BenchRunner<T>>,
-    mut stat: Arc<BenchStat>,
+    mut stat: BenchStatWriter,
     args: CmdlineArgs
 )
     where T: Send + 'static + Sync + Copy
@@ -93,12 +104,17 @@ pub fn perform_client_doorbell_routine<T>(
         Ok(res) => res,
         Err(()) => { panic!("Fail to bring up RC qp!") }
     };
+
+    runner.setup_done();
+    let clock = Clock::get();
     let mut rand = ChaCha8Rng::seed_from_u64(
         ((0xdeadbeaf + 73 * thread_id) as u64) + args.client_id * 37
     );
 
     let mut completions = [Default::default()];
     let mut pending: usize = 0;
+    // the time when the current signaled window starts
+    let mut window_start = clock.now();
     let mut rc_doorbell = RcDoorbellHelper::create(args.db_size, qp.clone());
     if args.read {
         rc_doorbell.init(ibv_wr_opcode::IBV_WR_RDMA_READ);
@@ -113,6 +129,9 @@ pub fn perform_client_doorbell_routine<T>(
             let index = args.get_next_index(thread_id, &mut rand);
             // start = (start + std::cmp::max(PAYLOAD, 64)) % ((LOCAL_MR - PAYLOAD) as u64);
             let signal = pending == 0;
+            if signal {
+                window_start = clock.now();
+            }
 
             rc_doorbell
                 .post_send(
@@ -140,18 +159,20 @@ pub fn perform_client_doorbell_routine<T>(
                     }
                 }
                 pending = 0;
+                // the unsignaled ops have no completion, so this is the latency of a window of `signal_size` ops
+                // (one sample per window), and the latency of each op only with --latency-test
+                stat.record_latency_ns(clock.elapsed_ns(window_start));
             }
         }
-        unsafe {
-            Arc::get_mut_unchecked(&mut stat).finished_batch_ops(args.factor);
-        }
+        stat.finished_batch_ops(args.factor);
+        stat.transferred_bytes(args.factor * args.payload);
     }
 }
 
 pub fn perform_client_signaled_routine<T>(
     thread_id: usize,
     runner: Arc<BenchRunner<T>>,
-    mut stat: Arc<BenchStat>,
+    mut stat: BenchStatWriter,
     args: CmdlineArgs
 )
     where T: Send + 'static + Sync + Copy
@@ -160,6 +181,9 @@ pub fn perform_client_signaled_routine<T>(
         Ok(res) => res,
         Err(()) => { panic!("Fail to bring up RC qp!") }
     };
+
+    runner.setup_done();
+    let clock = Clock::get();
     let mut rand = ChaCha8Rng::seed_from_u64(
         ((0xdeadbeaf + 73 * thread_id) as u64) + args.client_id * 37
     );
@@ -168,6 +192,7 @@ pub fn perform_client_signaled_routine<T>(
     while runner.running() {
         std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::Release);
         let mut start = 0;
+        let batch_start = clock.now();
         for i in 0..args.factor {
             let index = args.get_next_index(thread_id, &mut rand);
             // start = (start + std::cmp::max(PAYLOAD, 64)) % ((LOCAL_MR - PAYLOAD) as u64);
@@ -205,17 +230,17 @@ pub fn perform_client_signaled_routine<T>(
                     ok = true;
                 }
             }
+            stat.record_latency_ns(clock.elapsed_ns(batch_start));
         }
-        unsafe {
-            Arc::get_mut_unchecked(&mut stat).finished_batch_ops(args.factor);
-        }
+        stat.finished_batch_ops(args.factor);
+        stat.transferred_bytes(args.factor * args.payload);
     } // end of main benchmark loop
 }
 
 pub fn perform_client_doorbell_signaled_routine<T>(
     thread_id: usize,
     runner: Arc<BenchRunner<T>>,
-    mut stat: Arc<BenchStat>,
+    mut stat: BenchStatWriter,
     args: CmdlineArgs
 )
     where T: Send + 'static + Sync + Copy
@@ -225,12 +250,17 @@ pub fn perform_client_doorbell_signaled_routine<T>(
         Ok(res) => res,
         Err(()) => { panic!("Fail to bring up RC qp!") }
     };
+
+    runner.setup_done();
+    let clock = Clock::get();
     let mut rand = ChaCha8Rng::seed_from_u64(
         ((0xdeadbeaf + 73 * thread_id) as u64) + args.client_id * 37
     );
 
     let mut completions = [Default::default()];
     let mut pending: usize = 0;
+    // the time when the current signaled window starts
+    let mut window_start = clock.now();
     let mut rc_doorbell = RcDoorbellHelper::create(args.db_size, qp.clone());
     if args.read {
         rc_doorbell.init(ibv_wr_opcode::IBV_WR_RDMA_READ);
@@ -245,6 +275,9 @@ pub fn perform_client_doorbell_signaled_routine<T>(
             let index = args.get_next_index(thread_id, &mut rand);
             // start = (start + std::cmp::max(PAYLOAD, 64)) % ((LOCAL_MR - PAYLOAD) as u64);
             let signal = pending == 0;
+            if signal {
+                window_start = clock.now();
+            }
 
             rc_doorbell
                 .post_send(
@@ -271,10 +304,85 @@ pub fn perform_client_doorbell_signaled_routine<T>(
                     }
                 }
                 pending = 0;
+                stat.record_latency_ns(clock.elapsed_ns(window_start));
             }
         }
-        unsafe {
-            Arc::get_mut_unchecked(&mut stat).finished_batch_ops(args.factor);
+        stat.finished_batch_ops(args.factor);
+        stat.transferred_bytes(args.factor * args.payload);
+    }
+}
+
+/// The open-loop client (`--rate`): each op is posted at its intended send time,
+/// and its latency is measured from the intended send time, so the queueing delay is not omitted.
+/// At most `factor` ops are in flight, the ops due meanwhile wait (and their waits are measured).
+pub fn perform_client_open_loop_routine<T>(
+    thread_id: usize,
+    runner: Arc<BenchRunner<T>>,
+    mut stat: BenchStatWriter,
+    args: CmdlineArgs
+)
+    where T: Send + 'static + Sync + Copy
+{
+    let (qp, client_mr, server_meta) = match args.create_rc(thread_id) {
+        Ok(res) => res,
+        Err(()) => { panic!("Fail to bring up RC qp!") }
+    };
+
+    runner.setup_done();
+    let mut rand = ChaCha8Rng::seed_from_u64(
+        ((0xdeadbeaf + 73 * thread_id) as u64) + args.client_id * 37
+    );
+    let clock = Clock::get();
+    let mut limiter = runner.rate_limiter(thread_id).expect("not an open-loop run");
+    let mut completions = [Default::default(); 16];
+
+    let max_in_flight = args.factor as usize;
+    // the intended send times (clock ticks) of the ops in flight, every op is signaled and RC completes them in order
+    let mut in_flight: VecDeque<u64> = VecDeque::with_capacity(max_in_flight);
+    let mut wr_id: u64 = 0;
+
+    while runner.running() {
+        if in_flight.len() < max_in_flight {
+            if let Some(intended) = limiter.poll() {
+                let index = args.get_next_index(thread_id, &mut rand);
+                let start = (wr_id % args.factor) * args.payload;
+                if args.read {
+                    qp.post_send_read(
+                        &client_mr,
+                        start..start + args.payload,
+                        true,
+                        server_meta.addr + index,
+                        server_meta.rkey,
+                        wr_id
+                    ).expect("read should succeeed");
+                } else {
+                    qp.post_send_write(
+                        &client_mr,
+                        start..start + args.payload,
+                        true,
+                        server_meta.addr + index,
+                        server_meta.rkey,
+                        wr_id
+                    ).expect("write should succeeed");
+                }
+                in_flight.push_back(intended);
+                wr_id += 1;
+            }
         }
+
+        let ret = qp.poll_send_cq(&mut completions).expect("Failed to poll cq");
+        for wc in ret.iter() {
+            if wc.status != 0 {
+                error!("open-loop op {} err: {}", wc.wr_id, wc.status);
+            }
+            assert_eq!(wc.status, 0);
+            let intended = in_flight.pop_front().expect("a completion without an op in flight");
+            stat.record_latency_ns(clock.elapsed_ns(intended));
+        }
+        stat.finished_batch_ops(ret.len() as u64);
+        stat.transferred_bytes(ret.len() as u64 * args.payload);
     }
-}
\ No newline at end of file
+    if limiter.num_late() > 0 {
+        warn!("thread {}: {} ops are sent late, the target rate may be too high", thread_id, limiter.num_late());
+    }
+}
@@ -18,12 +18,13 @@ pub use client_construct::{
     perform_client_doorbell_routine,
     perform_client_signaled_routine,
     perform_client_doorbell_signaled_routine,
+    perform_client_open_loop_routine,
 };
 
 mod server_construct;
 pub use server_construct::perform_server_routine;
 
-use std::{ thread, time };
+use std::time;
 use std::time::Duration;
 use tokio::runtime::Runtime;
 
@@ -33,7 +34,11 @@ use bench_util::*;
 use netbencher_core::{
     CoordinatedReporterMaster,
     BenchRunner,
-    SimpleBenchReporter,
+    Shutdown,
+    Clock,
+    BenchPhases,
+    RunBound,
+    OpenLoop,
     CoordinatedReporter,
 };
 
@@ -41,8 +46,34 @@ use log::*;
 
 // Client bootstrap function
 pub fn bootstrap_client(args: CmdlineArgs) {
-    let mut runner = BenchRunner::new(args.threads.try_into().unwrap());
+    let runner = BenchRunner::new_with_phases(args.threads.try_into().unwrap(), BenchPhases::new(
+        Duration::from_secs(args.warmup.into()),
+        Duration::from_secs(args.life.into()),
+        Duration::from_secs(args.cooldown.into()),
+    ));
+    if let Some(placement) = &args.pin {
+        runner
+            .pin_workers(placement.clone())
+            .expect("failed to pin the workers");
+    }
+    if let Some(n) = args.max_ops {
+        runner.set_bound(RunBound::Ops(n));
+    } else if let Some(n) = args.max_bytes {
+        runner.set_bound(RunBound::Bytes(n));
+    }
+    if let Some(rate) = args.rate {
+        runner.set_open_loop(OpenLoop::new(rate, args.arrival));
+    }
+    // calibrate the clock once, before the workers time their ops
+    Clock::get();
+    let shutdown = Shutdown::install().expect("failed to install the Ctrl-C handler");
+    runner.stop_on(&shutdown);
     runner.run(|thread_id, runner, stat, args| {
+        if args.rate.is_some() {
+            info!("features: open-loop");
+            perform_client_open_loop_routine(thread_id, runner, stat, args);
+            return;
+        }
         match (args.doorbell, args.signaled) {
             (false, false) => {
                 perform_client_routine(thread_id, runner, stat, args);
@@ -62,7 +93,7 @@ pub fn bootstrap_client(args: CmdlineArgs) {
         }
     }, args.clone());
 
-    let mut inner_reporter = SimpleBenchReporter::new_with_id(args.client_id.try_into().unwrap());
+    let mut inner_reporter = args.build_reporter();
 
     if args.report {
         Runtime::new()
@@ -72,29 +103,62 @@ pub fn bootstrap_client(args: CmdlineArgs) {
                     args.report_addr.parse().unwrap(),
                     inner_reporter
                 ).await.expect("failed to create the reporter");
+                if args.controlled {
+                    runner.wait_setup();
+                }
+                reporter
+                    .register(Some(args.client_id.try_into().unwrap()))
+                    .await
+                    .expect("failed to register at the master");
+                if args.controlled {
+                    // on Ctrl-C, the runner is stopped, so the client goes on to its final report and summary
+                    if reporter
+                        .wait_start_until(shutdown.wait())
+                        .await
+                        .expect("failed to wait for the START of the master")
+                    {
+                        info!("started by the master");
+                    }
+                }
 
                 // send a report to the master
-                for epoch in 0..args.life {
-                    thread::sleep(time::Duration::from_secs(1));
+                for _phase in runner.phase_ticker(time::Duration::from_millis(args.report_interval)) {
                     runner.report_async(&mut reporter).await;
+                    if args.controlled && reporter.stop_requested() {
+                        info!("stopped by the master");
+                        break;
+                    }
+                }
+                // tell the master that this client finished cleanly,
+                // a failed client leaves without it, so that the master counts it as lost
+                if !runner.failed() {
+                    reporter.finish().await.expect("failed to send the final report");
                 }
             });
     } else {
-        for epoch in 0..args.life {
-            thread::sleep(time::Duration::from_secs(1));
+        for _phase in runner.phase_ticker(time::Duration::from_millis(args.report_interval)) {
             info!("{}", runner.report(&mut inner_reporter));
         }
     }
-    runner.stop().unwrap();
+    if let Err(e) = runner.stop() {
+        error!("the run failed: {}", e);
+        std::process::exit(1);
+    }
+    info!("summary: {}", runner.summary());
 }
 
 // Server bootstrap function
 pub fn bootstrap_server(mut args: CmdlineArgs) {
-    if args.life < MIN_SERVER_LIFE {
+    // the server outlives the warmup and cooldown of the clients
+    args.life += args.warmup + args.cooldown;
+    // a controlled run lasts for its life since START, and the server stops after the clients
+    if !args.controlled && args.life < MIN_SERVER_LIFE {
         args.life = MIN_SERVER_LIFE;
     }
 
-    let mut runner = BenchRunner::new(1);
+    let runner = BenchRunner::new(1);
+    let shutdown = Shutdown::install().expect("failed to install the Ctrl-C handler");
+    runner.stop_on(&shutdown);
     runner.run(|thread_id, runner, stat, args| { perform_server_routine(runner, args); }, args.clone());
     
     if args.report {
@@ -102,19 +166,29 @@ pub fn bootstrap_server(mut args: CmdlineArgs) {
             .unwrap()
             .block_on(async {
                 let mut master = CoordinatedReporterMaster::new(
-                    MAX_CLIENTS,
                     args.report_addr.parse().unwrap()
                 ).await.expect("failed to create the master");
+                args.configure_master(&mut master);
 
-                master
-                    .report_event_loop(
-                        Duration::from_secs(args.life.into()),
-                        Duration::from_secs(1)
-                    ).await
-                    .expect("event loop report error");
+                let life = Duration::from_secs(args.life.into());
+                let report_interval = Duration::from_millis(args.report_interval);
+                if args.controlled {
+                    master
+                        .controlled_event_loop_until(args.num_clients, life, report_interval, shutdown.wait())
+                        .await
+                        .expect("event loop report error");
+                } else {
+                    master
+                        .report_event_loop_until(life, report_interval, shutdown.wait())
+                        .await
+                        .expect("event loop report error");
+                }
             });
     } else {
-        thread::sleep(Duration::from_secs(args.life.into()));
+        shutdown.sleep(Duration::from_secs(args.life.into()));
+    }
+    if let Err(e) = runner.stop() {
+        error!("the run failed: {}", e);
+        std::process::exit(1);
     }
-    runner.stop().unwrap();
 }
\ No newline at end of file
@@ -1,4 +1,5 @@
 use std::sync::{ Arc };
+use std::collections::VecDeque;
 use std::borrow::Borrow;
 use bench_util::*;
 use bench_util::args::*;
@@ -6,9 +7,6 @@ use bench_util::doorbell::{ UdDoorbellHelper, RecvDoorbellHelper };
 use bench_util::ud_message::*;
 use bench_util::ud_endpoint::*;
 
-#[cfg(not(feature = "ARM"))]
-use bench_util::rdtsc::*;
-
 use netbencher_core::*;
 
 use KRdmaKit::rdma_shim::bindings::*;
@@ -19,7 +17,7 @@ use log::*;
 pub fn perform_client_routine<T>(
     thread_id: usize,
     runner: Arc<BenchRunner<T>>,
-    mut stat: Arc<BenchStat>,
+    mut stat: BenchStatWriter,
     client_qp: Arc<QueuePair>,
     server_ep: Arc<DatagramEndpoint>,
     args: CmdlineArgs
@@ -57,6 +55,9 @@ pub fn perform_client_routine<T>(
             .expect("recv should succ");
     }
 
+    runner.setup_done();
+    let clock = Clock::get();
+
     let mut completions = [Default::default(); MAX_FLYING_MSG as usize];
     let mut pending: usize = 0;
     let batch_or_not = if args.latency_test { 1 } else { args.signal_size };
@@ -67,6 +68,7 @@ pub fn perform_client_routine<T>(
     while runner.running() {
         std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::Release);
         let req_batch = if args.latency_test { 1 } else { args.factor };
+        let batch_start = clock.now();
         for i in 0..req_batch {
             let signal = pending == 0;
             let start = ud_buffer.get_start_addr();
@@ -112,9 +114,12 @@ pub fn perform_client_routine<T>(
                     -remaining
                 );
             }
-            unsafe {
-                Arc::get_mut_unchecked(&mut stat).finished_batch_ops(recv_msg_num);
-            }
+            stat.finished_batch_ops(recv_msg_num);
+            // the requests of the batch are sent together, so each reply takes the time since the batch starts
+            stat.record_batch_latency_ns(clock.elapsed_ns(batch_start), recv_msg_num);
+            // request payload + reply payload (the GRH is excluded)
+            let reply_bytes: u64 = recv.iter().map(|wc| (wc.byte_len as u64).saturating_sub(GRH_SZ)).sum();
+            stat.transferred_bytes(recv_msg_num * payload + reply_bytes);
         }
     }
 }
@@ -122,7 +127,7 @@ pub fn perform_client_routine<T>(
 pub fn perform_client_profile_routine<T>(
     thread_id: usize,
     runner: Arc<BenchRunner<T>>,
-    mut stat: Arc<BenchStat>,
+    mut stat: BenchStatWriter,
     client_qp: Arc<QueuePair>,
     server_ep: Arc<DatagramEndpoint>,
     args: CmdlineArgs
@@ -161,21 +166,26 @@ pub fn perform_client_profile_routine<T>(
             .expect("recv should succ");
     }
 
+    runner.setup_done();
+    let clock = Clock::get();
+
     let mut completions = [Default::default(); MAX_FLYING_MSG as usize];
     let mut pending: usize = 0;
     let batch_or_not = if args.latency_test { 1 } else { args.signal_size };
     // encode client message to imm so that server can know who to reply to
     let imm_data = encode_id(args.client_id as _, thread_id as _);
     let payload = align_to_cacheline(args.payload);
+    // the time spent in posting each request
+    let mut post_latency = LatencyHistogram::new();
     // each loop send args.factor UD msgs and wait for their replies
     while runner.running() {
         std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::Release);
         let req_batch = if args.latency_test { 1 } else { args.factor };
+        let batch_start = clock.now();
         for i in 0..req_batch {
             let signal = pending == 0;
             let start = ud_buffer.get_start_addr();
-            #[cfg(not(feature = "ARM"))] 
-            let begin_ts = get_rdtsc();
+            let post_start = clock.now();
 
             client_qp
                 .post_datagram_w_imm(
@@ -187,13 +197,7 @@ pub fn perform_client_profile_routine<T>(
                     signal
                 )
                 .expect("send should succeeed");
-            #[cfg(not(feature = "ARM"))]
-            {
-                let end_ts = get_rdtsc();
-                unsafe {
-                    Arc::get_mut_unchecked(&mut stat).record_avg_rdtsc((end_ts - begin_ts).try_into().unwrap());
-                }
-            }   
+            post_latency.record(clock.elapsed_ns(post_start));
             
             pending += 1;
             recv_doorbell
@@ -226,17 +230,28 @@ pub fn perform_client_profile_routine<T>(
                     -remaining
                 );
             }
-            unsafe {
-                Arc::get_mut_unchecked(&mut stat).finished_batch_ops(recv_msg_num);
-            }
+            stat.finished_batch_ops(recv_msg_num);
+            // the requests of the batch are sent together, so each reply takes the time since the batch starts
+            stat.record_batch_latency_ns(clock.elapsed_ns(batch_start), recv_msg_num);
+            // request payload + reply payload (the GRH is excluded)
+            let reply_bytes: u64 = recv.iter().map(|wc| (wc.byte_len as u64).saturating_sub(GRH_SZ)).sum();
+            stat.transferred_bytes(recv_msg_num * payload + reply_bytes);
         }
     }
+    info!(
+        "thread {} post latency: avg {:.0} ns, p50 {} ns, p99 {} ns, max {} ns",
+        thread_id,
+        post_latency.mean(),
+        post_latency.value_at_quantile(0.5),
+        post_latency.value_at_quantile(0.99),
+        post_latency.max()
+    );
 }
 
 pub fn perform_client_doorbell_routine<T>(
     thread_id: usize,
     runner: Arc<BenchRunner<T>>,
-    mut stat: Arc<BenchStat>,
+    mut stat: BenchStatWriter,
     client_qp: Arc<QueuePair>,
     server_ep: Arc<DatagramEndpoint>,
     args: CmdlineArgs
@@ -278,6 +293,9 @@ pub fn perform_client_doorbell_routine<T>(
             .expect("recv should succ");
     }
 
+    runner.setup_done();
+    let clock = Clock::get();
+
     let mut completions = [Default::default(); MAX_FLYING_MSG as usize];
     let mut pending: usize = 0;
     let batch_or_not = if args.latency_test { 1 } else { args.signal_size };
@@ -288,6 +306,7 @@ pub fn perform_client_doorbell_routine<T>(
     while runner.running() {
         std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::Release);
         let req_batch = if args.latency_test { 1 } else { args.factor };
+        let batch_start = clock.now();
         for i in 0..req_batch {
             let signal = pending == 0;
             let start = ud_buffer.get_start_addr();
@@ -333,9 +352,116 @@ pub fn perform_client_doorbell_routine<T>(
                     -remaining
                 );
             }
-            unsafe {
-                Arc::get_mut_unchecked(&mut stat).finished_batch_ops(recv_msg_num);
+            stat.finished_batch_ops(recv_msg_num);
+            // the requests of the batch are sent together, so each reply takes the time since the batch starts
+            stat.record_batch_latency_ns(clock.elapsed_ns(batch_start), recv_msg_num);
+            // request payload + reply payload (the GRH is excluded)
+            let reply_bytes: u64 = recv.iter().map(|wc| (wc.byte_len as u64).saturating_sub(GRH_SZ)).sum();
+            stat.transferred_bytes(recv_msg_num * payload + reply_bytes);
+        }
+    }
+}
+
+/// The open-loop client (`--rate`): each request is sent at its intended send time,
+/// and its latency is measured from the intended send time to its reply, so the queueing delay is not omitted.
+/// At most `factor` requests are in flight, the requests due meanwhile wait (and their waits are measured).
+pub fn perform_client_open_loop_routine<T>(
+    thread_id: usize,
+    runner: Arc<BenchRunner<T>>,
+    mut stat: BenchStatWriter,
+    client_qp: Arc<QueuePair>,
+    server_ep: Arc<DatagramEndpoint>,
+    args: CmdlineArgs
+)
+    where T: Send + 'static + Sync + Copy
+{
+    let ctx = client_qp.ctx();
+    let mut ud_buffer = UdBuffer::new(MAX_FLYING_MSG, MAX_MSG_SZ);
+    let region_size = ud_buffer.get_region_size();
+    let (send_mr, recv_mr) = if args.huge_page {
+        (
+            MemoryRegion::new_huge_page(ctx.clone(), region_size as _).expect(
+                "Failed to allocate hugepage MR for send buffer"
+            ),
+            MemoryRegion::new_huge_page(ctx.clone(), region_size as _).expect(
+                "Failed to allocate hugepage MR for send buffer"
+            ),
+        )
+    } else {
+        (
+            MemoryRegion::new(ctx.clone(), region_size as _).expect(
+                "Failed to allocate MR for send buffer"
+            ),
+            MemoryRegion::new(ctx.clone(), region_size as _).expect(
+                "Fail to allocate MR for recv buffer"
+            ),
+        )
+    };
+
+    let mut recv_doorbell = RecvDoorbellHelper::create(MAX_RECV_NUM, client_qp.clone());
+    for wr_id in 0..MAX_FLYING_MSG {
+        let start = ud_buffer.get_start_addr();
+        recv_doorbell
+            .post_recv(&recv_mr, start..start + MAX_MSG_SZ, wr_id)
+            .expect("recv should succ");
+    }
+
+    runner.setup_done();
+    let clock = Clock::get();
+    let mut limiter = runner.rate_limiter(thread_id).expect("not an open-loop run");
+
+    let mut completions = [Default::default(); MAX_FLYING_MSG as usize];
+    let mut send_completions = [Default::default(); MAX_FLYING_MSG as usize];
+    // encode client message to imm so that server can know who to reply to
+    let imm_data = encode_id(args.client_id as _, thread_id as _);
+    let payload = align_to_cacheline(args.payload);
+    let max_in_flight = std::cmp::min(args.factor, MAX_FLYING_MSG) as usize;
+    // the intended send times (clock ticks) of the requests in flight,
+    // the server thread serving this client thread replies in order
+    let mut in_flight: VecDeque<u64> = VecDeque::with_capacity(max_in_flight);
+    let mut wr_id: u64 = 0;
+
+    while runner.running() {
+        if in_flight.len() < max_in_flight {
+            if let Some(intended) = limiter.poll() {
+                let start = ud_buffer.get_start_addr();
+                client_qp
+                    .post_datagram_w_imm(
+                        server_ep.borrow(),
+                        &send_mr,
+                        start..start + payload,
+                        wr_id,
+                        imm_data,
+                        true
+                    )
+                    .expect("send should succeeed");
+                recv_doorbell
+                    .post_recv(&recv_mr, start..start + MAX_MSG_SZ, wr_id)
+                    .expect("recv should succ");
+                in_flight.push_back(intended);
+                wr_id += 1;
             }
         }
+
+        let sent = client_qp.poll_send_cq(&mut send_completions).expect("Failed to poll cq");
+        if let Some(wc) = sent.iter().find(|wc| wc.status != 0) {
+            panic!("cq status: {}", wc.status);
+        }
+
+        let recv = client_qp.poll_recv_cq(&mut completions).unwrap();
+        for _ in 0..recv.len() {
+            let intended = in_flight
+                .pop_front()
+                .expect("Wrong in your programming, reply to an false client");
+            stat.record_latency_ns(clock.elapsed_ns(intended));
+        }
+        let recv_msg_num = recv.len() as u64;
+        stat.finished_batch_ops(recv_msg_num);
+        // request payload + reply payload (the GRH is excluded)
+        let reply_bytes: u64 = recv.iter().map(|wc| (wc.byte_len as u64).saturating_sub(GRH_SZ)).sum();
+        stat.transferred_bytes(recv_msg_num * payload + reply_bytes);
     }
-}
\ No newline at end of file
+    if limiter.num_late() > 0 {
+        warn!("thread {}: {} requests are sent late, the target rate may be too high", thread_id, limiter.num_late());
+    }
+}
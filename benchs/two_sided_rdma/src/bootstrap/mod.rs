@@ -3,12 +3,13 @@ pub use client_construct::{
     perform_client_routine, 
     perform_client_doorbell_routine,
     perform_client_profile_routine,
+    perform_client_open_loop_routine,
 };
 
 mod server_construct;
 pub use server_construct::{ perform_server_routine, perform_server_doorbell_routine };
 
-use std::{ thread, time };
+use std::time;
 use std::net::{ SocketAddr, TcpStream };
 use std::time::Duration;
 use std::collections::HashMap;
@@ -24,7 +25,11 @@ use bench_util::ud_manager::*;
 use netbencher_core::{
     CoordinatedReporterMaster,
     BenchRunner,
-    SimpleBenchReporter,
+    Shutdown,
+    Clock,
+    BenchPhases,
+    RunBound,
+    OpenLoop,
     CoordinatedReporter,
 };
 
@@ -43,16 +48,55 @@ pub fn bootstrap_client(args: CmdlineArgs) {
         args.client_id
     );
 
-    let mut runner = BenchRunner::new(args.threads.try_into().unwrap());
-    runner.run(move |thread_id, runner, stat, args| {
+    let runner = BenchRunner::new_with_phases(args.threads.try_into().unwrap(), BenchPhases::new(
+        Duration::from_secs(args.warmup.into()),
+        Duration::from_secs(args.life.into()),
+        Duration::from_secs(args.cooldown.into()),
+    ));
+    if let Some(placement) = &args.pin {
+        runner
+            .pin_workers(placement.clone())
+            .expect("failed to pin the workers");
+    }
+    if let Some(n) = args.max_ops {
+        runner.set_bound(RunBound::Ops(n));
+    } else if let Some(n) = args.max_bytes {
+        runner.set_bound(RunBound::Bytes(n));
+    }
+    if let Some(rate) = args.rate {
+        runner.set_open_loop(OpenLoop::new(rate, args.arrival));
+    }
+    // calibrate the clock once, before the workers time their ops
+    Clock::get();
+    let shutdown = Shutdown::install().expect("failed to install the Ctrl-C handler");
+    runner.stop_on(&shutdown);
+    // each worker takes its own qp and the endpoint of its server thread
+    let inputs = client_qps
+        .into_iter()
+        .zip(server_eps)
+        .map(|(client_qp, server_ep)| (client_qp, server_ep, args.clone()))
+        .collect();
+    runner.run_with_inputs(|thread_id, runner, stat, (client_qp, server_ep, args)| {
+        if args.rate.is_some() {
+            info!("features: open-loop");
+            perform_client_open_loop_routine(
+                thread_id,
+                runner,
+                stat,
+                client_qp,
+                server_ep,
+                args
+            );
+            return;
+        }
         match (args.profile, args.doorbell) {
             (false, false) => {
                 perform_client_routine(
                     thread_id, 
                     runner, 
                     stat,
-                    client_qps[thread_id].clone(),
-                    server_eps[thread_id].clone(), 
+                    client_qp,
+                    server_ep,
                     args
                 );
             }
@@ -62,8 +106,8 @@ pub fn bootstrap_client(args: CmdlineArgs) {
                     thread_id, 
                     runner, 
                     stat, 
-                    client_qps[thread_id].clone(),
-                    server_eps[thread_id].clone(),
+                    client_qp,
+                    server_ep,
                     args
                 );
             }
@@ -73,8 +117,8 @@ pub fn bootstrap_client(args: CmdlineArgs) {
                     thread_id, 
                     runner, 
                     stat, 
-                    client_qps[thread_id].clone(),
-                    server_eps[thread_id].clone(),
+                    client_qp,
+                    server_ep,
                     args
                 );
             }
@@ -82,9 +126,9 @@ pub fn bootstrap_client(args: CmdlineArgs) {
                 warn!("We dont support profiling doorbell send for now!");
             }
         }
-    }, args.clone());
+    }, inputs);
 
-    let mut inner_reporter = SimpleBenchReporter::new_with_id(args.client_id.try_into().unwrap());
+    let mut inner_reporter = args.build_reporter();
 
     if args.report {
         Runtime::new()
@@ -94,24 +138,55 @@ pub fn bootstrap_client(args: CmdlineArgs) {
                     args.report_addr.parse().unwrap(),
                     inner_reporter
                 ).await.expect("failed to create the reporter");
+                if args.controlled {
+                    runner.wait_setup();
+                }
+                reporter
+                    .register(Some(args.client_id.try_into().unwrap()))
+                    .await
+                    .expect("failed to register at the master");
+                if args.controlled {
+                    // on Ctrl-C, the runner is stopped, so the client goes on to its final report and summary
+                    if reporter
+                        .wait_start_until(shutdown.wait())
+                        .await
+                        .expect("failed to wait for the START of the master")
+                    {
+                        info!("started by the master");
+                    }
+                }
 
                 // send a report to the master
-                for epoch in 0..args.life {
-                    thread::sleep(time::Duration::from_secs(1));
+                for _phase in runner.phase_ticker(time::Duration::from_millis(args.report_interval)) {
                     runner.report_async(&mut reporter).await;
+                    if args.controlled && reporter.stop_requested() {
+                        info!("stopped by the master");
+                        break;
+                    }
+                }
+                // tell the master that this client finished cleanly,
+                // a failed client leaves without it, so that the master counts it as lost
+                if !runner.failed() {
+                    reporter.finish().await.expect("failed to send the final report");
                 }
             });
     } else {
-        for epoch in 0..args.life {
-            thread::sleep(time::Duration::from_secs(1));
+        for _phase in runner.phase_ticker(time::Duration::from_millis(args.report_interval)) {
             info!("{}", runner.report(&mut inner_reporter));
         }
     }
-    runner.stop().unwrap();
+    if let Err(e) = runner.stop() {
+        error!("the run failed: {}", e);
+        std::process::exit(1);
+    }
+    info!("summary: {}", runner.summary());
 }
 
 pub fn bootstrap_server(mut args: CmdlineArgs) {
-    if args.life < MIN_SERVER_LIFE {
+    // the server outlives the warmup and cooldown of the clients
+    args.life += args.warmup + args.cooldown;
+    // a controlled run lasts for its life since START, and the server stops after the clients
+    if !args.controlled && args.life < MIN_SERVER_LIFE {
         args.life = MIN_SERVER_LIFE;
     }
 
@@ -123,18 +198,26 @@ pub fn bootstrap_server(mut args: CmdlineArgs) {
     
     // create a copy for closure
     let conn_meta_ptr = conn_meta.clone();
-    let mut runner = BenchRunner::new(args.threads.try_into().unwrap());
-    runner.run(move |thread_id, runner, stat, args| {
+    let runner = BenchRunner::new(args.threads.try_into().unwrap());
+    if let Some(placement) = &args.pin {
+        runner
+            .pin_workers(placement.clone())
+            .expect("failed to pin the workers");
+    }
+    let shutdown = Shutdown::install().expect("failed to install the Ctrl-C handler");
+    runner.stop_on(&shutdown);
+    let inputs = qps.into_iter().map(|qp| (qp, args.clone())).collect();
+    runner.run_with_inputs(move |_, runner, stat, (qp, args)| {
         match args.doorbell {
             false => {
-                perform_server_routine(runner, qps[thread_id].clone(), conn_meta_ptr.clone(), args);
+                perform_server_routine(runner, qp, conn_meta_ptr.clone(), args);
             }
             true => {
                 info!("features: doorbell");
-                perform_server_doorbell_routine(runner, qps[thread_id].clone(), conn_meta_ptr.clone(), args);
+                perform_server_doorbell_routine(runner, qp, conn_meta_ptr.clone(), args);
             }
         }
-    }, args.clone());
+    }, inputs);
 
     // serialize meta infos of server's UD qps into message
     let metas_msg = marshal_batch(metas, 0);
@@ -148,24 +231,34 @@ pub fn bootstrap_server(mut args: CmdlineArgs) {
             .unwrap()
             .block_on(async {
                 let mut master = CoordinatedReporterMaster::new(
-                    MAX_CLIENTS,
                     args.report_addr.parse().unwrap()
                 ).await.expect("failed to create the master");
+                args.configure_master(&mut master);
 
-                master
-                    .report_event_loop(
-                        Duration::from_secs(args.life.into()),
-                        Duration::from_secs(1)
-                    ).await
-                    .expect("event loop report error");
+                let life = Duration::from_secs(args.life.into());
+                let report_interval = Duration::from_millis(args.report_interval);
+                if args.controlled {
+                    master
+                        .controlled_event_loop_until(args.num_clients, life, report_interval, shutdown.wait())
+                        .await
+                        .expect("event loop report error");
+                } else {
+                    master
+                        .report_event_loop_until(life, report_interval, shutdown.wait())
+                        .await
+                        .expect("event loop report error");
+                }
             });
     } else {
-        thread::sleep(Duration::from_secs(args.life.into()));
+        shutdown.sleep(Duration::from_secs(args.life.into()));
     }
 
     // stop listening and exit
     ud_manager.stop_listen();
     listen_thread.join();
-    runner.stop().unwrap();
+    if let Err(e) = runner.stop() {
+        error!("the run failed: {}", e);
+        std::process::exit(1);
+    }
     info!("Server exit.");
 }
\ No newline at end of file
@@ -2,13 +2,13 @@ extern crate netbencher_core;
 
 use std::time::Duration;
 
-use clap::{Command, Arg, arg};
+use clap::{Command, Arg};
 use simplelog::*;
 use log::{info, LevelFilter};
 
 use tokio::runtime::Runtime;
 
-use netbencher_core::CoordinatedReporterMaster;
+use netbencher_core::{CoordinatedReporterMaster, StatFile};
 
 fn main() {
     TermLogger::init(
@@ -19,12 +19,6 @@ fn main() {
     ).unwrap();
 
     let matches = Command::new("bench example master")
-        .arg(
-            Arg::new("num_reports")
-                .short('n')
-                .long("num_reporters")
-                .default_value("1"),
-        )
         .arg(
             Arg::new("listen_addr")
                 .short('r')
@@ -35,27 +29,54 @@ fn main() {
             Arg::new("duration_secs")
                 .short('d')
                 .long("duration_secs")
+                .value_parser(clap::value_parser!(u64))
                 .default_value("20"),
         )
+        .arg(
+            Arg::new("report_interval_ms")
+                .short('i')
+                .long("report_interval_ms")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1000"),
+        )
+        .arg(
+            Arg::new("controlled")
+                .short('c')
+                .long("controlled")
+                .value_parser(clap::value_parser!(usize))
+                .help("wait for this number of reporters, then START and STOP them"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output"),
+        )
         .get_matches();
 
     Runtime::new().unwrap().block_on(async {
         let mut master = CoordinatedReporterMaster::new(
-            *matches.get_one("num_reports")
-                .expect("failed to get num reports"),
             matches.get_one::<String>("listen_addr").unwrap().to_string()
                 .parse().unwrap(),
         )
         .await
         .expect("failed to create the master");
 
-        master
-            .report_event_loop(
-                Duration::from_secs(*matches.get_one("duration_secs").unwrap()),
-                Duration::from_secs(1),
-            )
-            .await
-            .expect("Event loop report error");
+        if let Some(path) = matches.get_one::<String>("output") {
+            master.set_output(StatFile::create(path).expect("failed to create the output file"));
+        }
+
+        let duration = Duration::from_secs(*matches.get_one("duration_secs").unwrap());
+        let report_interval = Duration::from_millis(*matches.get_one("report_interval_ms").unwrap());
+        match matches.get_one::<usize>("controlled") {
+            Some(num_reporters) => master
+                .controlled_event_loop(*num_reporters, duration, report_interval)
+                .await
+                .expect("Event loop report error"),
+            None => master
+                .report_event_loop(duration, report_interval)
+                .await
+                .expect("Event loop report error"),
+        }
     });
 
     info!("Master done");
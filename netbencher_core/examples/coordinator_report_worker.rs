@@ -1,11 +1,8 @@
-#![feature(get_mut_unchecked)]
-
 extern crate netbencher_core;
 
-use clap::{Command, Arg, arg};
+use clap::{Command, Arg};
 use simplelog::*;
 use log::{info, warn, LevelFilter};
-use std::sync::Arc;
 use tokio::runtime::Runtime;
 
 use netbencher_core::{BenchRunner, CoordinatedReporter, SimpleBenchReporter};
@@ -24,6 +21,7 @@ fn main() {
             Arg::new("num_workers")
                 .short('n')
                 .long("num_workers")
+                .value_parser(clap::value_parser!(usize))
                 .default_value("1"),
         )
         .arg(
@@ -36,11 +34,19 @@ fn main() {
             Arg::new("id")
                 .short('i')
                 .long("id")
+                .value_parser(clap::value_parser!(usize))
                 .default_value("0"),
         )
+        .arg(
+            Arg::new("controlled")
+                .short('c')
+                .long("controlled")
+                .action(clap::ArgAction::SetTrue)
+                .help("run from the START to the STOP of the master"),
+        )
         .get_matches();
 
-    let mut runner = BenchRunner::new(
+    let runner = BenchRunner::new(
         *matches.get_one("num_workers")
                 .expect("failed to get num workers"),
     );
@@ -50,7 +56,7 @@ fn main() {
             info!("Worker {} started", worker_id);
             while runner.running() {
                 std::thread::sleep(std::time::Duration::from_secs(1));
-                unsafe { Arc::get_mut_unchecked(&mut stats).finished_one_op() };
+                stats.finished_one_op();
             }
         },
         (),
@@ -69,15 +75,31 @@ fn main() {
         )
         .await
         .expect("failed to create the reporter");
+        reporter
+            .register(Some(*matches.get_one("id").unwrap()))
+            .await
+            .expect("failed to register at the master");
 
-        for _ in 0..10 {
-            std::thread::sleep(std::time::Duration::from_secs(1));
-            let stat = runner.report_async(&mut reporter).await;
-            // println!("Results: {}", stat);
+        if matches.get_flag("controlled") {
+            reporter.wait_start().await.expect("failed to wait for the master to start");
+            info!("started by the master");
+            while !reporter.stop_requested() {
+                std::thread::sleep(std::time::Duration::from_secs(1));
+                let stat = runner.report_async(&mut reporter).await;
+                info!("Results: {}", stat);
+            }
+        } else {
+            for _ in 0..10 {
+                std::thread::sleep(std::time::Duration::from_secs(1));
+                let stat = runner.report_async(&mut reporter).await;
+                info!("Results: {}", stat);
+            }
         }
+        reporter.finish().await.expect("failed to send the final report");
     });
 
     runner.stop().unwrap();
 
+    info!("Summary: {}", runner.summary());
     info!("done");
 }
@@ -0,0 +1,368 @@
+//! Pin the workers to the cores, so that the results are less noisy (especially on the SmartNIC SoCs).
+//!
+//! The cores of each worker are decided by a [`CorePlacement`] (see [`crate::BenchRunner::pin_workers`]),
+//! among the cores the process is allowed to run on (e.g., by `taskset` or the cgroup),
+//! with the NUMA topology read from `/sys/devices/system/node`.
+//! A worker runs on `cores[thread_id % cores.len()]`, so more workers than cores share the cores.
+//!
+//! ```no_run
+//! use netbencher_core::{BenchRunner, CorePlacement};
+//!
+//! let runner = BenchRunner::new(4);
+//! // run on the NUMA node of the first RDMA NIC
+//! runner.pin_workers(CorePlacement::Nic(0)).expect("failed to place the workers");
+//! runner.run(|thread_id, runner, mut stat, _| {
+//!     println!("worker {} runs on core {:?}", thread_id, runner.core_of(thread_id));
+//!     while runner.running() {
+//!         stat.finished_one_op();
+//!     }
+//! }, ());
+//! runner.stop().unwrap();
+//! ```
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::str::FromStr;
+
+use log::{info, warn};
+
+/// The sysfs directory of the NUMA nodes
+const NODE_DIR: &str = "/sys/devices/system/node";
+
+/// The sysfs directory of the RDMA devices
+const IB_DEVICE_DIR: &str = "/sys/class/infiniband";
+
+/// Where to run the workers
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub enum CorePlacement {
+    /// Let the OS schedule the workers
+    #[default]
+    Unpinned,
+    /// Worker `i` runs on the `i`-th core of the list
+    Cores(Vec<usize>),
+    /// Fill the cores of one NUMA node before the next one
+    Compact,
+    /// Place the workers round-robin across the NUMA nodes
+    Scatter,
+    /// Run on the cores of a NUMA node
+    NumaNode(usize),
+    /// Run on the cores of the NUMA node of the `n`-th RDMA device, in the order of their names
+    /// (e.g., `mlx5_0`, `mlx5_1`, ..., `mlx5_10`), see [`nic_numa_node`]
+    Nic(usize),
+    /// Run on the cores of the NUMA node of the RDMA device with the name, e.g., `mlx5_1`,
+    /// see [`nic_device_numa_node`]
+    NicDevice(String),
+}
+
+impl FromStr for CorePlacement {
+    type Err = String;
+
+    /// Parse `unpinned`, `compact`, `scatter`, `node:<n>`, `nic:<n>`, `nic:<device>` (e.g., `nic:mlx5_1`),
+    /// or a core list, e.g., `0,2,4-7`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let index = |v: &str| {
+            v.parse::<usize>()
+                .map_err(|e| format!("invalid index {}: {}", v, e))
+        };
+        match s {
+            "unpinned" => Ok(Self::Unpinned),
+            "compact" => Ok(Self::Compact),
+            "scatter" => Ok(Self::Scatter),
+            _ => {
+                if let Some(n) = s.strip_prefix("node:") {
+                    Ok(Self::NumaNode(index(n)?))
+                } else if let Some(n) = s.strip_prefix("nic:") {
+                    if n.is_empty() || n.contains('/') {
+                        Err(format!("invalid NIC {}", n))
+                    } else if n.bytes().all(|b| b.is_ascii_digit()) {
+                        Ok(Self::Nic(index(n)?))
+                    } else {
+                        Ok(Self::NicDevice(n.to_string()))
+                    }
+                } else {
+                    parse_cpu_list(s)
+                        .map(Self::Cores)
+                        .map_err(|e| format!("invalid placement {}: {}", s, e))
+                }
+            }
+        }
+    }
+}
+
+/// The cores the process can run on, and the NUMA node of each of them
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Topology {
+    // core -> NUMA node, ordered by the core ids
+    nodes: BTreeMap<usize, usize>,
+}
+
+impl Topology {
+    /// Read the topology of this machine.
+    /// Without the NUMA information in sysfs, all the cores are on node 0.
+    pub fn detect() -> std::io::Result<Self> {
+        let allowed = allowed_cores()?;
+        let mut nodes: BTreeMap<usize, usize> = allowed.iter().map(|&c| (c, 0)).collect();
+        if let Ok(dir) = std::fs::read_dir(NODE_DIR) {
+            for entry in dir.flatten() {
+                let name = entry.file_name();
+                let node = match name.to_str().and_then(|n| n.strip_prefix("node")) {
+                    Some(n) => match n.parse::<usize>() {
+                        Ok(n) => n,
+                        Err(_) => continue,
+                    },
+                    None => continue,
+                };
+                let cpulist = std::fs::read_to_string(entry.path().join("cpulist"))?;
+                for core in parse_cpu_list(cpulist.trim())? {
+                    if let Some(n) = nodes.get_mut(&core) {
+                        *n = node;
+                    }
+                }
+            }
+        }
+        Ok(Self { nodes })
+    }
+
+    /// The cores of each worker under a placement, empty if the workers are not pinned
+    pub fn assign(&self, placement: &CorePlacement, num_workers: usize) -> std::io::Result<Vec<usize>> {
+        let cores: Vec<usize> = match placement {
+            CorePlacement::Unpinned => return Ok(Vec::new()),
+            CorePlacement::Cores(cores) => {
+                if let Some(c) = cores.iter().find(|c| !self.nodes.contains_key(c)) {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("core {} is not available to the process", c),
+                    ));
+                }
+                cores.clone()
+            }
+            CorePlacement::Compact => {
+                let mut cores: Vec<usize> = self.nodes.keys().copied().collect();
+                cores.sort_by_key(|c| (self.nodes[c], *c));
+                cores
+            }
+            CorePlacement::Scatter => {
+                // interleave the cores of the nodes
+                let per_node = self.cores_per_node();
+                let max_len = per_node.values().map(|c| c.len()).max().unwrap_or(0);
+                (0..max_len)
+                    .flat_map(|i| per_node.values().filter_map(move |c| c.get(i).copied()))
+                    .collect()
+            }
+            CorePlacement::NumaNode(node) => self.cores_of_node(*node)?,
+            CorePlacement::Nic(n) => match nic_numa_node(*n)? {
+                Some(node) => self.cores_of_node(node)?,
+                None => {
+                    warn!("the NUMA node of NIC {} is unknown, place the workers compactly", n);
+                    return self.assign(&CorePlacement::Compact, num_workers);
+                }
+            },
+            CorePlacement::NicDevice(device) => match nic_device_numa_node(device)? {
+                Some(node) => self.cores_of_node(node)?,
+                None => {
+                    warn!("the NUMA node of NIC {} is unknown, place the workers compactly", device);
+                    return self.assign(&CorePlacement::Compact, num_workers);
+                }
+            },
+        };
+        if cores.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "no core to place the workers"));
+        }
+        if num_workers > cores.len() {
+            warn!(
+                "{} workers share {} cores under the placement {:?}",
+                num_workers,
+                cores.len(),
+                placement
+            );
+        }
+        Ok((0..num_workers).map(|i| cores[i % cores.len()]).collect())
+    }
+
+    fn cores_per_node(&self) -> BTreeMap<usize, Vec<usize>> {
+        let mut per_node: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (&core, &node) in self.nodes.iter() {
+            per_node.entry(node).or_default().push(core);
+        }
+        per_node
+    }
+
+    fn cores_of_node(&self, node: usize) -> std::io::Result<Vec<usize>> {
+        self.cores_per_node().remove(&node).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("no available core on NUMA node {}", node),
+            )
+        })
+    }
+}
+
+/// The NUMA node of the `n`-th RDMA device, see [`nic_device_numa_node`].
+///
+/// The devices are ordered by their names, with the trailing numbers compared as numbers,
+/// i.e., `mlx5_2` comes before `mlx5_10`.
+/// The device the index resolves to is logged, name the device with [`CorePlacement::NicDevice`] to avoid surprises.
+pub fn nic_numa_node(n: usize) -> std::io::Result<Option<usize>> {
+    let mut devices: Vec<String> = std::fs::read_dir(IB_DEVICE_DIR)?
+        .flatten()
+        .filter_map(|e| e.file_name().into_string().ok())
+        .collect();
+    sort_devices(&mut devices);
+    let device = devices.get(n).ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            format!("NIC {} not found, RDMA devices: {:?}", n, devices),
+        )
+    })?;
+    info!("NIC {} is {} (RDMA devices: {:?})", n, device, devices);
+    nic_device_numa_node(device)
+}
+
+/// The NUMA node of the RDMA device with the name (e.g., `mlx5_1`),
+/// read from `/sys/class/infiniband/<dev>/device/numa_node`.
+///
+/// Return `None` if the node is unknown, e.g., on a machine without NUMA.
+pub fn nic_device_numa_node(device: &str) -> std::io::Result<Option<usize>> {
+    let path = Path::new(IB_DEVICE_DIR).join(device).join("device/numa_node");
+    let node = std::fs::read_to_string(path).map_err(|e| {
+        Error::new(e.kind(), format!("failed to read the NUMA node of NIC {}: {}", device, e))
+    })?;
+    // -1 if the node is unknown
+    Ok(node.trim().parse::<usize>().ok())
+}
+
+/// Sort the device names, comparing their trailing numbers as numbers
+fn sort_devices(devices: &mut [String]) {
+    devices.sort_by_cached_key(|d| {
+        let prefix = d.trim_end_matches(|c: char| c.is_ascii_digit());
+        (prefix.to_string(), d[prefix.len()..].parse::<u64>().ok(), d.clone())
+    });
+}
+
+/// Parse a CPU list of sysfs or taskset, e.g., `0-3,8,10-11`
+fn parse_cpu_list(s: &str) -> std::io::Result<Vec<usize>> {
+    let invalid = || Error::new(ErrorKind::InvalidData, format!("invalid cpu list: {}", s));
+    let mut cores = Vec::new();
+    for range in s.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+        match range.split_once('-') {
+            Some((start, end)) => {
+                let start: usize = start.parse().map_err(|_| invalid())?;
+                let end: usize = end.parse().map_err(|_| invalid())?;
+                if start > end {
+                    return Err(invalid());
+                }
+                cores.extend(start..=end);
+            }
+            None => cores.push(range.parse().map_err(|_| invalid())?),
+        }
+    }
+    Ok(cores)
+}
+
+/// The cores the process is allowed to run on
+#[cfg(target_os = "linux")]
+fn allowed_cores() -> std::io::Result<Vec<usize>> {
+    // SAFETY: the set is a plain bitmap, which is valid when zeroed
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    // SAFETY: the size matches the set
+    if unsafe { libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) } != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok((0..libc::CPU_SETSIZE as usize)
+        // SAFETY: the core is within the set
+        .filter(|&c| unsafe { libc::CPU_ISSET(c, &set) })
+        .collect())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn allowed_cores() -> std::io::Result<Vec<usize>> {
+    Ok((0..std::thread::available_parallelism()?.get()).collect())
+}
+
+/// Pin the calling thread to a core
+#[cfg(target_os = "linux")]
+pub(crate) fn pin_current_thread(core: usize) -> std::io::Result<()> {
+    // SAFETY: the set is a plain bitmap, which is valid when zeroed
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    // SAFETY: the core is checked against the size of the set
+    unsafe {
+        if core >= libc::CPU_SETSIZE as usize {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("invalid core {}", core)));
+        }
+        libc::CPU_SET(core, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn pin_current_thread(_core: usize) -> std::io::Result<()> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        "thread pinning is only supported on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{parse_cpu_list, sort_devices, CorePlacement, Topology};
+
+    /// 2 nodes, node 0: cores 0-3, node 1: cores 4-7
+    fn two_nodes() -> Topology {
+        Topology {
+            nodes: (0..8).map(|c| (c, c / 4)).collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn test_parse_placement() {
+        assert_eq!(parse_cpu_list("0-3,8, 10-11\n".trim()).unwrap(), vec![0, 1, 2, 3, 8, 10, 11]);
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("a").is_err());
+
+        assert_eq!("compact".parse(), Ok(CorePlacement::Compact));
+        assert_eq!("scatter".parse(), Ok(CorePlacement::Scatter));
+        assert_eq!("node:1".parse(), Ok(CorePlacement::NumaNode(1)));
+        assert_eq!("nic:0".parse(), Ok(CorePlacement::Nic(0)));
+        assert_eq!("nic:mlx5_1".parse(), Ok(CorePlacement::NicDevice("mlx5_1".to_string())));
+        assert_eq!("1,3-4".parse(), Ok(CorePlacement::Cores(vec![1, 3, 4])));
+        assert_eq!("nic:x".parse(), Ok(CorePlacement::NicDevice("x".to_string())));
+        assert!("nic:".parse::<CorePlacement>().is_err());
+        assert!("nic:../mlx5_0".parse::<CorePlacement>().is_err());
+        assert!("nic:99999999999999999999".parse::<CorePlacement>().is_err());
+
+        let mut devices: Vec<String> = ["mlx5_10", "mlx5_2", "ib0", "mlx5_0"].map(String::from).to_vec();
+        sort_devices(&mut devices);
+        assert_eq!(devices, ["ib0", "mlx5_0", "mlx5_2", "mlx5_10"]);
+    }
+
+    #[test]
+    fn test_assign_cores() {
+        let topo = two_nodes();
+        assert_eq!(topo.assign(&CorePlacement::Unpinned, 4).unwrap(), Vec::<usize>::new());
+        assert_eq!(topo.assign(&CorePlacement::Compact, 5).unwrap(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(topo.assign(&CorePlacement::Scatter, 5).unwrap(), vec![0, 4, 1, 5, 2]);
+        assert_eq!(topo.assign(&CorePlacement::NumaNode(1), 3).unwrap(), vec![4, 5, 6]);
+        // more workers than cores
+        assert_eq!(
+            topo.assign(&CorePlacement::Cores(vec![6, 2]), 3).unwrap(),
+            vec![6, 2, 6]
+        );
+
+        assert!(topo.assign(&CorePlacement::NumaNode(2), 1).is_err());
+        assert!(topo.assign(&CorePlacement::Cores(vec![8]), 1).is_err());
+        assert!(topo.assign(&CorePlacement::Cores(vec![]), 1).is_err());
+    }
+
+    #[test]
+    fn test_detect_topology() {
+        let topo = Topology::detect().unwrap();
+        let cores = topo.assign(&CorePlacement::Compact, 1).unwrap();
+        assert_eq!(cores.len(), 1);
+        super::pin_current_thread(cores[0]).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+//! A cheap, portable clock to time the ops, e.g., a post or a round trip.
+//!
+//! The clock reads the cycle counter of the CPU: `rdtsc` on x86_64, and `cntvct_el0` on aarch64
+//! (e.g., the Arm cores of a BlueField SoC), or falls back to `clock_gettime(CLOCK_MONOTONIC)` elsewhere.
+//! The counter is calibrated against the monotonic clock once per process, at the first [`Clock::get`],
+//! so that reading and converting the ticks is as cheap as reading the counter:
+//!
+//! ```no_run
+//! use netbencher_core::{BenchRunner, Clock};
+//!
+//! let runner = BenchRunner::new(1);
+//! runner.run(|_, runner, mut stat, _| {
+//!     let clock = Clock::get();
+//!     while runner.running() {
+//!         let start = clock.now();
+//!         // send the op, and wait for its completion ...
+//!         stat.finished_one_op();
+//!         stat.record_latency_ns(clock.elapsed_ns(start));
+//!     }
+//! }, ());
+//! runner.stop().unwrap();
+//! ```
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// How long to count the ticks to calibrate the clock
+const CALIBRATION_PERIOD: Duration = Duration::from_millis(20);
+
+static CLOCK: OnceLock<Clock> = OnceLock::new();
+
+/// The counter read by a [`Clock`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockSource {
+    /// The time-stamp counter of x86_64
+    Rdtsc,
+    /// The virtual counter of aarch64
+    CntVct,
+    /// `clock_gettime(CLOCK_MONOTONIC)` in nanoseconds
+    Monotonic,
+}
+
+impl ClockSource {
+    /// The source of this platform
+    pub fn current() -> Self {
+        if cfg!(target_arch = "x86_64") {
+            Self::Rdtsc
+        } else if cfg!(target_arch = "aarch64") {
+            Self::CntVct
+        } else {
+            Self::Monotonic
+        }
+    }
+}
+
+/// A calibrated clock, whose readings are ticks of its [`ClockSource`]
+#[derive(Clone, Copy, Debug)]
+pub struct Clock {
+    source: ClockSource,
+    ns_per_tick: f64,
+}
+
+impl Clock {
+    /// The clock of the process, calibrated at the first call
+    pub fn get() -> &'static Clock {
+        CLOCK.get_or_init(|| {
+            let clock = Self::calibrate(CALIBRATION_PERIOD);
+            log::info!(
+                "clock: {:?} at {:.3} ticks/ns",
+                clock.source,
+                clock.ticks_per_ns()
+            );
+            clock
+        })
+    }
+
+    /// Count the ticks of the counter against the monotonic clock during `period`
+    pub fn calibrate(period: Duration) -> Self {
+        let source = ClockSource::current();
+        if source == ClockSource::Monotonic {
+            return Self {
+                source,
+                ns_per_tick: 1.0,
+            };
+        }
+        let (begin, begin_ticks) = (Instant::now(), read_ticks());
+        std::thread::sleep(period);
+        let (end, end_ticks) = (Instant::now(), read_ticks());
+        Self {
+            source,
+            ns_per_tick: (end - begin).as_nanos() as f64 / end_ticks.wrapping_sub(begin_ticks).max(1) as f64,
+        }
+    }
+
+    /// The source of the clock
+    pub fn source(&self) -> ClockSource {
+        self.source
+    }
+
+    /// The frequency of the clock, in ticks per nanosecond (i.e., GHz)
+    pub fn ticks_per_ns(&self) -> f64 {
+        1.0 / self.ns_per_tick
+    }
+
+    /// Read the clock, in ticks
+    #[inline(always)]
+    pub fn now(&self) -> u64 {
+        read_ticks()
+    }
+
+    /// Convert a number of ticks to nanoseconds
+    #[inline]
+    pub fn ticks_to_ns(&self, ticks: u64) -> u64 {
+        (ticks as f64 * self.ns_per_tick) as u64
+    }
+
+    /// Convert a number of nanoseconds to ticks
+    #[inline]
+    pub fn ns_to_ticks(&self, ns: u64) -> u64 {
+        (ns as f64 / self.ns_per_tick) as u64
+    }
+
+    /// The nanoseconds since the reading `start`
+    #[inline]
+    pub fn elapsed_ns(&self, start: u64) -> u64 {
+        self.ticks_to_ns(self.now().saturating_sub(start))
+    }
+
+    /// The time since the reading `start`
+    #[inline]
+    pub fn elapsed(&self, start: u64) -> Duration {
+        Duration::from_nanos(self.elapsed_ns(start))
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+fn read_ticks() -> u64 {
+    // SAFETY: rdtsc is available on all x86_64 CPUs
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline(always)]
+fn read_ticks() -> u64 {
+    let ticks: u64;
+    // SAFETY: cntvct_el0 is readable at EL0 on Linux
+    unsafe {
+        core::arch::asm!("mrs {}, cntvct_el0", out(reg) ticks, options(nomem, nostack));
+    }
+    ticks
+}
+
+#[cfg(all(unix, not(any(target_arch = "x86_64", target_arch = "aarch64"))))]
+#[inline(always)]
+fn read_ticks() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: ts is a valid timespec
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+    }
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+#[cfg(not(any(unix, target_arch = "x86_64", target_arch = "aarch64")))]
+#[inline(always)]
+fn read_ticks() -> u64 {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    EPOCH.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Clock, ClockSource};
+
+    #[test]
+    fn test_clock() {
+        let clock = Clock::get();
+        assert_eq!(clock.source(), ClockSource::current());
+        // calibrated once
+        assert!(std::ptr::eq(clock, Clock::get()));
+        assert!(clock.ticks_per_ns() > 0.0);
+
+        let start = clock.now();
+        let begin = Instant::now();
+        std::thread::sleep(Duration::from_millis(50));
+        let measured = clock.elapsed(start);
+        let expected = begin.elapsed();
+        // the clock agrees with the monotonic clock within 10%
+        let error = (measured.as_secs_f64() - expected.as_secs_f64()).abs();
+        assert!(
+            error < expected.as_secs_f64() / 10.0,
+            "measured {:?}, expected {:?}",
+            measured,
+            expected
+        );
+
+        // a reading is never in the past
+        assert_eq!(clock.elapsed_ns(clock.now() + 1_000_000), 0);
+    }
+}
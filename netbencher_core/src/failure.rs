@@ -0,0 +1,101 @@
+//! The failures of the workers of a runner.
+//!
+//! A worker fails if it panics, e.g., on a bad completion.
+//! The runner catches the panic, stops all the other workers at once (so that the reporters do not keep
+//! printing the throughput of a broken run), and [`crate::BenchRunner::stop`] returns the failures:
+//!
+//! ```no_run
+//! use netbencher_core::BenchRunner;
+//!
+//! let runner = BenchRunner::new(4);
+//! runner.run(|thread_id, runner, mut stat, _| {
+//!     while runner.running() {
+//!         assert!(thread_id != 3, "bad completion");
+//!         stat.finished_one_op();
+//!     }
+//! }, ());
+//! if let Err(e) = runner.stop() {
+//!     eprintln!("{}", e);
+//!     std::process::exit(1);
+//! }
+//! ```
+use std::any::Any;
+
+/// The failure of one worker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerFailure {
+    /// The id of the worker, i.e., its thread id in the runner
+    pub worker: usize,
+    /// The panic message of the worker
+    pub message: String,
+}
+
+impl WorkerFailure {
+    pub(crate) fn from_panic(worker: usize, payload: &(dyn Any + Send)) -> Self {
+        let message = if let Some(s) = payload.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            String::from("unknown panic")
+        };
+        Self { worker, message }
+    }
+}
+
+impl std::fmt::Display for WorkerFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "worker {} failed: {}", self.worker, self.message)
+    }
+}
+
+/// The error of a run whose workers failed, in the order they failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunError {
+    /// The failed workers, the first one is the failure that stopped the run
+    pub failures: Vec<WorkerFailure>,
+}
+
+impl RunError {
+    /// The failure that stopped the run
+    pub fn first(&self) -> &WorkerFailure {
+        &self.failures[0]
+    }
+}
+
+impl std::fmt::Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.first())?;
+        if self.failures.len() > 1 {
+            write!(f, " (and {} more workers failed)", self.failures.len() - 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RunError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{RunError, WorkerFailure};
+
+    #[test]
+    fn test_failure_message() {
+        let payload: Box<dyn std::any::Any + Send> = Box::new("bad completion");
+        let a = WorkerFailure::from_panic(3, payload.as_ref());
+        assert_eq!(a.to_string(), "worker 3 failed: bad completion");
+
+        let payload: Box<dyn std::any::Any + Send> = Box::new(format!("status {}", 12));
+        let b = WorkerFailure::from_panic(0, payload.as_ref());
+        assert_eq!(b.message, "status 12");
+
+        let payload: Box<dyn std::any::Any + Send> = Box::new(42);
+        assert_eq!(WorkerFailure::from_panic(1, payload.as_ref()).message, "unknown panic");
+
+        let err = RunError { failures: vec![a.clone()] };
+        assert_eq!(err.to_string(), "worker 3 failed: bad completion");
+        let err = RunError { failures: vec![a, b] };
+        assert_eq!(err.first().worker, 3);
+        assert_eq!(err.to_string(), "worker 3 failed: bad completion (and 1 more workers failed)");
+    }
+}
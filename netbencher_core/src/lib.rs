@@ -1,11 +1,10 @@
-#![feature(get_mut_unchecked, async_fn_in_trait)]
 //! Core code for bootstrap the benchmark
 //!
 //! The code represents a client process running on a client machine.
 //! We use a framework to simplfiy bootstraping the tests.
 //!
 //! The framework ([`BenchRunner`]) will bootstrap a set of (pre-defined) threads to run user-specificed thread body functions.
-//! The function is expceted to record some statistics ([`BenchStat`])]),
+//! The function is expceted to record some statistics through its [`BenchStatWriter`],
 //! and the framework will automatically collect and report these numbers ([`CollectedBenchStat`]).
 //!
 //! The [`BenchReporter`] trait will implement various strategies to report the statistics,
@@ -19,7 +18,7 @@
 //! ```no_run
 //! use netbencher_core::BenchRunner;
 //!
-//! let mut runner = BenchRunner::new(4);
+//! let runner = BenchRunner::new(4);
 //! runner.run(|thread_id, runner, stat, input| {
 //!    // do something
 //!    // mark the stats
@@ -35,7 +34,7 @@
 //! use netbencher_core::BenchRunner;
 //! use netbencher_core::SimpleBenchReporter;
 //!
-//! let mut runner = BenchRunner::new(4);
+//! let runner = BenchRunner::new(4);
 //! runner.run(|thread_id, runner, stat, input| {
 //!     while runner.running() {
 //!       // do something
@@ -60,90 +59,605 @@
 
 #![deny(missing_docs)]
 
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 /// Reporter will implement the BenchReporter related modules
 mod reporter;
 pub use reporter::{
-    AsyncBenchReporter, BenchReporter, BenchStat, CollectedBenchStat, CoordinatedReporter,
-    CoordinatedReporterMaster, SimpleBenchReporter,
+    AsyncBenchReporter, BenchReporter, BenchStat, BenchStatWriter, BenchSummary,
+    CollectedBenchStat, CollectedMetric, CoordinatedReporter, CoordinatedReporterMaster,
+    FileReporter, LatencyHistogram, Metric, MetricId, MetricKind, OutputFormat,
+    PrometheusExporter, PrometheusReporter, ReportFuture, SimpleBenchReporter, Smoothing,
+    SmoothingReporter, StatFile, TeeReporter, ThresholdReporter, ThroughputSummary,
+    WorkerThroughput,
 };
+use reporter::{MetricDesc, SharedBenchStat};
+
+/// Phases of a run, i.e., setup, warmup, measure and cooldown
+mod phase;
+pub use phase::{BenchPhase, BenchPhases, PhaseTicker, RunBound};
+use phase::{MeasuredWindow, BOUND_CHECK_INTERVAL, SETUP_SPINS};
+
+/// Pin the workers to the cores
+mod affinity;
+pub use affinity::{nic_device_numa_node, nic_numa_node, CorePlacement};
+use affinity::Topology;
+
+/// Open-loop load generation
+mod rate;
+pub use rate::{Arrival, OpenLoop, RateLimiter};
+
+/// The failures of the workers
+mod failure;
+pub use failure::{RunError, WorkerFailure};
+
+/// Graceful shutdown on Ctrl-C
+mod shutdown;
+pub use shutdown::Shutdown;
+
+/// A calibrated cycle-counter clock
+mod clock;
+pub use clock::{Clock, ClockSource};
 
 /// Global control data structure to manage the bench workers
 /// T : the return type of the worker
 ///
 pub struct BenchRunner<T> {
-    handlers: Vec<JoinHandle<T>>,
-    worker_stats: Vec<Arc<BenchStat>>,
+    // a worker returns `None` if it panics
+    handlers: Mutex<Vec<JoinHandle<Option<T>>>>,
+    failures: Mutex<Vec<WorkerFailure>>,
+    interrupted: AtomicBool,
+    worker_stats: Mutex<Vec<Arc<SharedBenchStat>>>,
+    metrics: Mutex<Vec<MetricDesc>>,
+    // the core of each worker, empty if the workers are not pinned
+    cores: Mutex<Vec<usize>>,
+    open_loop: Mutex<Option<OpenLoop>>,
+    bound: Mutex<Option<RunBound>>,
+    bound_reached: AtomicBool,
+    // the thread checking the bound of the run
+    watcher: Mutex<Option<JoinHandle<()>>>,
     num_workers: usize,
     running: AtomicBool,
+    phase: AtomicU8,
+    phases: BenchPhases,
+    num_ready: AtomicUsize,
+    // the workers sleeping at the setup barrier, woken up when the phase changes or the runner stops
+    setup_lock: Mutex<()>,
+    setup_cond: Condvar,
+    measured: Mutex<MeasuredWindow>,
+    interval_throughputs: Mutex<Vec<f64>>,
 }
 
 impl<T> BenchRunner<T> {
-    /// Create a new bench runner with a given number of workers
+    /// Create a new bench runner with a given number of workers.
+    ///
+    /// The runner has no explicit phases: the whole run is measured.
     pub fn new(num_workers: usize) -> Arc<Self> {
+        Self::new_inner(num_workers, BenchPhase::Measure, Default::default())
+    }
+
+    /// Create a new bench runner with a given number of workers, which goes through the given phases.
+    ///
+    /// The runner starts at [`BenchPhase::Setup`], and the phases are driven by [`BenchRunner::phase_ticker`].
+    pub fn new_with_phases(num_workers: usize, phases: BenchPhases) -> Arc<Self> {
+        Self::new_inner(num_workers, BenchPhase::Setup, phases)
+    }
+
+    fn new_inner(num_workers: usize, phase: BenchPhase, phases: BenchPhases) -> Arc<Self> {
         Arc::new(Self {
-            handlers: Vec::new(),
-            worker_stats: Vec::new(),
+            handlers: Mutex::new(Vec::new()),
+            failures: Mutex::new(Vec::new()),
+            interrupted: AtomicBool::new(false),
+            worker_stats: Mutex::new(Vec::new()),
+            metrics: Mutex::new(Vec::new()),
+            cores: Mutex::new(Vec::new()),
+            open_loop: Mutex::new(None),
+            bound: Mutex::new(None),
+            bound_reached: AtomicBool::new(false),
+            watcher: Mutex::new(None),
             num_workers,
             running: AtomicBool::new(true),
+            phase: AtomicU8::new(phase.as_u8()),
+            phases,
+            num_ready: AtomicUsize::new(0),
+            setup_lock: Mutex::new(()),
+            setup_cond: Condvar::new(),
+            measured: Mutex::new(MeasuredWindow::default()),
+            interval_throughputs: Mutex::new(Vec::new()),
         })
     }
 
+    /// Register a counter metric that workers can increase with [`BenchStatWriter::add_counter`].
+    ///
+    /// # Panics
+    /// The metrics must be registered before [`BenchRunner::run`].
+    pub fn register_counter(&self, name: &str) -> MetricId {
+        self.register_metric(name, MetricKind::Counter)
+    }
+
+    /// Register a gauge metric that workers can set with [`BenchStatWriter::set_gauge`].
+    ///
+    /// # Panics
+    /// The metrics must be registered before [`BenchRunner::run`].
+    pub fn register_gauge(&self, name: &str) -> MetricId {
+        self.register_metric(name, MetricKind::Gauge)
+    }
+
+    fn register_metric(&self, name: &str, kind: MetricKind) -> MetricId {
+        assert!(
+            self.worker_stats.lock().unwrap().is_empty(),
+            "metrics must be registered before the runner runs"
+        );
+        let mut metrics = self.metrics.lock().unwrap();
+        if let Some(i) = metrics.iter().position(|m| m.name == name) {
+            assert_eq!(metrics[i].kind, kind, "metric {} registered twice", name);
+            return MetricId(i);
+        }
+        metrics.push(MetricDesc {
+            name: name.to_string(),
+            kind,
+        });
+        MetricId(metrics.len() - 1)
+    }
+
+    /// Pin the workers to the cores decided by the placement, see [`CorePlacement`].
+    ///
+    /// Return an error if the placement can not be satisfied on this machine, e.g., the NUMA node has no core.
+    ///
+    /// # Panics
+    /// The workers must be pinned before [`BenchRunner::run`].
+    pub fn pin_workers(&self, placement: CorePlacement) -> std::io::Result<()> {
+        assert!(
+            self.worker_stats.lock().unwrap().is_empty(),
+            "workers must be pinned before the runner runs"
+        );
+        let cores = Topology::detect()?.assign(&placement, self.num_workers)?;
+        *self.cores.lock().unwrap() = cores;
+        Ok(())
+    }
+
+    /// The core that a worker is pinned to, or `None` if the workers are not pinned
+    pub fn core_of(&self, thread_id: usize) -> Option<usize> {
+        self.cores.lock().unwrap().get(thread_id).copied()
+    }
+
+    /// Run the workers open-loop at a target aggregate rate, which is split evenly across the workers,
+    /// see [`BenchRunner::rate_limiter`]
+    pub fn set_open_loop(&self, open_loop: OpenLoop) {
+        *self.open_loop.lock().unwrap() = Some(open_loop);
+    }
+
+    /// The open-loop setting of the run, if any
+    pub fn open_loop(&self) -> Option<OpenLoop> {
+        *self.open_loop.lock().unwrap()
+    }
+
+    /// The rate limiter of a worker in an open-loop run, or `None` if the run is closed-loop.
+    ///
+    /// The schedule starts when the limiter is created, so create it after the setup (e.g., after [`BenchRunner::setup_done`]).
+    pub fn rate_limiter(&self, thread_id: usize) -> Option<RateLimiter> {
+        self.open_loop().map(|o| {
+            RateLimiter::new(
+                o.ops_per_sec / self.num_workers as f64,
+                o.arrival,
+                thread_id as u64,
+            )
+        })
+    }
+
+    /// Bound the measured window by the ops (or bytes) finished by all the workers, e.g., to issue 10M READs.
+    ///
+    /// The measured window ends once the bound is reached, or when its duration passes, whichever comes first,
+    /// and [`BenchRunner::summary`] reports the achieved duration.
+    /// The run goes on with the cooldown if any, otherwise the workers are stopped, i.e., [`BenchRunner::running`] becomes false.
+    /// The bound is checked by a background thread every 100µs, so the workers may overshoot it by the ops of about 100µs.
+    ///
+    /// # Panics
+    /// The bound must be set before [`BenchRunner::run`].
+    pub fn set_bound(&self, bound: RunBound) {
+        assert!(
+            self.worker_stats.lock().unwrap().is_empty(),
+            "the bound must be set before the runner runs"
+        );
+        *self.bound.lock().unwrap() = Some(bound);
+    }
+
+    /// The bound of the run, if any
+    pub fn bound(&self) -> Option<RunBound> {
+        *self.bound.lock().unwrap()
+    }
+
+    /// Whether the measured window has ended by the bound of the run
+    #[inline]
+    pub fn bound_reached(&self) -> bool {
+        self.bound_reached.load(Ordering::Acquire)
+    }
+
     /// Run a given function on each worker
     ///
     /// The passsed in function is expected to take the signature of the following:
     ///
-    /// fn worker(thread_id : usize, runner : Arc <BenchRunner<T>>, stat : Arc<BenchStat>, input : Input) -> T
+    /// fn worker(thread_id : usize, runner : Arc <BenchRunner<T>>, stat : BenchStatWriter, input : Input) -> T
+    ///
+    /// If a worker panics, the runner stops all the workers, and [`BenchRunner::stop`] returns the failure.
+    ///
+    /// Each worker gets a clone of `input`, see [`BenchRunner::run_with_inputs`] to give each worker its own input.
+    ///
+    pub fn run<F, Input>(self: &Arc<Self>, func: F, input: Input)
+    where
+        F: FnOnce(usize, Arc<Self>, BenchStatWriter, Input) -> T + Send + 'static + Clone,
+        T: Send + 'static,
+        Input: Send + 'static + Clone,
+    {
+        self.run_with_factory(func, |_| input.clone())
+    }
+
+    /// Run a given function on each worker, the i-th worker takes the i-th input,
+    /// e.g., the queue pair of the thread.
     ///
-    pub fn run<F, Input>(self: &mut Arc<Self>, func: F, input: Input)
+    /// # Panics
+    /// There must be one input for each worker.
+    pub fn run_with_inputs<F, Input>(self: &Arc<Self>, func: F, inputs: Vec<Input>)
     where
-        F: FnOnce(usize, Arc<Self>, Arc<BenchStat>, Input) -> T + Send + 'static + Clone,
-        T: Send + 'static + Sync + Copy,
-        Input: Send + 'static + Sync + Clone,
+        F: FnOnce(usize, Arc<Self>, BenchStatWriter, Input) -> T + Send + 'static + Clone,
+        T: Send + 'static,
+        Input: Send + 'static,
     {
-        let runner = self.clone();
-        let self_mut = unsafe { Arc::get_mut_unchecked(self) };
-
-        for i in 0..runner.num_workers {
-            let inner_runner = runner.clone();
-            let stat: Arc<BenchStat> = Arc::new(Default::default());
-            self_mut.worker_stats.push(stat.clone());
-            let input_args = input.clone();
+        assert_eq!(
+            inputs.len(),
+            self.num_workers,
+            "expect one input for each worker"
+        );
+        let mut inputs = inputs.into_iter();
+        self.run_with_factory(func, move |_| inputs.next().unwrap())
+    }
+
+    /// Run a given function on each worker, whose input is created by `factory(thread_id)`
+    /// on the calling thread, before the worker starts.
+    pub fn run_with_factory<F, Input, G>(self: &Arc<Self>, func: F, mut factory: G)
+    where
+        F: FnOnce(usize, Arc<Self>, BenchStatWriter, Input) -> T + Send + 'static + Clone,
+        T: Send + 'static,
+        Input: Send + 'static,
+        G: FnMut(usize) -> Input,
+    {
+        // without explicit phases, the whole run is measured
+        if self.phase() == BenchPhase::Measure {
+            self.mark_measured(true);
+        }
+
+        let mut handlers = self.handlers.lock().unwrap();
+        let mut worker_stats = self.worker_stats.lock().unwrap();
+        let metrics: Arc<[MetricDesc]> = self.metrics.lock().unwrap().clone().into();
+
+        for i in 0..self.num_workers {
+            let inner_runner = self.clone();
+            let core = self.core_of(i);
+            let (stat, shared_stat) = BenchStatWriter::new(metrics.clone(), core);
+            worker_stats.push(shared_stat);
+            let input_args = factory(i);
             let func = func.clone();
-            let handler = std::thread::spawn(move || func(i, inner_runner, stat, input_args));
-            self_mut.handlers.push(handler);
+            let handler = std::thread::spawn(move || {
+                if let Some(core) = core {
+                    if let Err(e) = affinity::pin_current_thread(core) {
+                        log::warn!("failed to pin worker {} to core {}: {}", i, core, e);
+                    }
+                }
+                let runner = inner_runner.clone();
+                match std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+                    func(i, inner_runner, stat, input_args)
+                })) {
+                    Ok(res) => Some(res),
+                    Err(payload) => {
+                        runner.fail(WorkerFailure::from_panic(i, payload.as_ref()));
+                        None
+                    }
+                }
+            });
+            handlers.push(handler);
         }
+
+        if let Some(bound) = self.bound() {
+            let runner = self.clone();
+            *self.watcher.lock().unwrap() = Some(std::thread::spawn(move || runner.watch_bound(bound)));
+        }
+    }
+
+    /// Check the bound of the run until it is reached, the measured window ends, or the runner stops
+    fn watch_bound(&self, bound: RunBound) {
+        while self.running() {
+            let start = {
+                let measured = self.measured.lock().unwrap();
+                if measured.end.is_some() {
+                    return;
+                }
+                measured
+                    .start
+                    .as_ref()
+                    .map(|(t, s)| (*t, s.num_ops_finished, s.num_bytes))
+            };
+            if let Some((start_time, start_ops, start_bytes)) = start {
+                let (num_ops, num_bytes) = self.progress();
+                if bound.reached(num_ops - start_ops, num_bytes - start_bytes) {
+                    self.mark_measured(false);
+                    self.bound_reached.store(true, Ordering::Release);
+                    log::info!(
+                        "the run reached {:?} in {:?}",
+                        bound,
+                        start_time.elapsed()
+                    );
+                    if self.phases.cooldown.is_zero() {
+                        self.halt();
+                    }
+                    return;
+                }
+            }
+            std::thread::sleep(BOUND_CHECK_INTERVAL);
+        }
+    }
+
+    /// The ops and bytes finished by all the workers so far
+    fn progress(&self) -> (u64, u64) {
+        self.worker_stats
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|s| s.progress())
+            .fold((0, 0), |(ops, bytes), (o, b)| (ops + o, bytes + b))
+    }
+
+    /// Record the failure of a worker, and stop all the other workers
+    fn fail(&self, failure: WorkerFailure) {
+        log::error!("{}, stopping all the workers", failure);
+        self.failures.lock().unwrap().push(failure);
+        self.halt();
     }
 
-    /// Stop all the workers
-    pub fn stop(self: &mut Arc<Self>) -> std::thread::Result<Vec<T>> {
+    /// Check if any worker has failed
+    pub fn failed(&self) -> bool {
+        !self.failures.lock().unwrap().is_empty()
+    }
+
+    /// The failures of the workers so far, in the order they failed
+    pub fn failures(&self) -> Vec<WorkerFailure> {
+        self.failures.lock().unwrap().clone()
+    }
+
+    /// Stop the workers once `shutdown` is triggered, e.g., on Ctrl-C (see [`Shutdown::install`]).
+    ///
+    /// The workers see it through [`BenchRunner::running`], and the `phase_ticker` ends after a final tick,
+    /// so that the bench reports the last interval, then stops the runner and prints its summary as usual.
+    pub fn stop_on(self: &Arc<Self>, shutdown: &Shutdown)
+    where
+        T: Send + 'static,
+    {
+        let runner = Arc::downgrade(self);
+        shutdown.on_shutdown(move || {
+            if let Some(runner) = runner.upgrade() {
+                runner.interrupt();
+            }
+        });
+    }
+
+    fn interrupt(&self) {
+        log::warn!("interrupted, stopping all the workers");
+        self.interrupted.store(true, Ordering::SeqCst);
+        self.halt();
+    }
+
+    /// Check if the run has been stopped by a shutdown, see [`BenchRunner::stop_on`]
+    pub fn interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+
+    /// Stop all the workers, and return their results in the order of the workers,
+    /// or the failures if any of them failed
+    pub fn stop(self: &Arc<Self>) -> Result<Vec<T>, RunError> {
         let mut res = Vec::new();
 
-        let self_mut = unsafe { Arc::get_mut_unchecked(self) };
-        self_mut.running.store(false, Ordering::SeqCst);
+        self.halt();
+
+        let mut handlers = self.handlers.lock().unwrap();
+        for handler in handlers.drain(..) {
+            // the panics of the workers are caught, see `run`
+            if let Some(r) = handler.join().unwrap() {
+                res.push(r);
+            }
+        }
+        if let Some(watcher) = self.watcher.lock().unwrap().take() {
+            watcher.join().unwrap();
+        }
 
-        while !self_mut.handlers.is_empty() {
-            res.push(self_mut.handlers.pop().unwrap().join()?);
+        // all the workers have exited, so the final stats are visible
+        if self.phase() == BenchPhase::Measure {
+            self.mark_measured(false);
         }
 
+        let failures = self.failures();
+        if !failures.is_empty() {
+            return Err(RunError { failures });
+        }
         Ok(res)
     }
 
+    /// Stop all the workers, and merge their results with `reduce` in the order of the workers,
+    /// e.g., to merge the histograms recorded by each worker.
+    ///
+    /// Return `None` if the runner has no workers, or the failures if any of them failed.
+    pub fn stop_and_reduce<R>(self: &Arc<Self>, reduce: R) -> Result<Option<T>, RunError>
+    where
+        R: FnMut(T, T) -> T,
+    {
+        Ok(self.stop()?.into_iter().reduce(reduce))
+    }
+
+    /// Take a snapshot of the stats of all the managed workers
+    pub fn worker_stats(&self) -> Vec<BenchStat> {
+        self.worker_stats
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|s| s.snapshot())
+            .collect()
+    }
+
     /// Report the collected stats from the managed workers
-    pub fn report(self: &Self, reporter: &mut dyn BenchReporter) -> CollectedBenchStat {
-        reporter.report_collected_stat(&self.worker_stats)
+    pub fn report(&self, reporter: &mut dyn BenchReporter) -> CollectedBenchStat {
+        reporter.set_phase(self.phase());
+        let res = reporter.report_collected_stat(&self.worker_stats());
+        self.record_interval(&res);
+        res
     }
 
     /// Report the collected stats from the managed workers (async version)
-    /// Due to compile problem of async trait, currently I will fix the reporter to be CoordinatedReporter
-    pub async fn report_async<R: BenchReporter>(
-        self: &Self,
-        reporter: &mut CoordinatedReporter<R>,
-    ) -> CollectedBenchStat {
-        reporter.async_report_collect_stat(&self.worker_stats).await
+    pub async fn report_async(&self, reporter: &mut dyn AsyncBenchReporter) -> CollectedBenchStat {
+        reporter.set_phase(self.phase());
+        let stats = self.worker_stats();
+        let res = reporter.async_report_collect_stat(&stats).await;
+        self.record_interval(&res);
+        res
+    }
+
+    /// The summary of the measured window, which covers the whole run after [`BenchRunner::stop`].
+    ///
+    /// The per-interval throughputs are the ones reported (by [`BenchRunner::report`]) in the measured window.
+    pub fn summary(&self) -> BenchSummary {
+        let total = self.measured_stat().unwrap_or_default();
+        BenchSummary::new(&self.interval_throughputs.lock().unwrap(), &total)
+    }
+
+    fn record_interval(&self, stat: &CollectedBenchStat) {
+        if stat.phase == BenchPhase::Measure {
+            self.interval_throughputs
+                .lock()
+                .unwrap()
+                .push(stat.throughput);
+        }
+    }
+
+    /// The stat finished during the measured window, i.e., excluding the setup, warmup and cooldown.
+    /// If the window has not ended yet, the stat up to now is returned.
+    ///
+    /// Return `None` if the measured window has not started.
+    pub fn measured_stat(&self) -> Option<CollectedBenchStat> {
+        let measured = self.measured.lock().unwrap();
+        let (start_time, start) = measured.start.clone()?;
+        let (end_time, end) = measured
+            .end
+            .clone()
+            .unwrap_or_else(|| (Instant::now(), self.merged_stat()));
+        Some(CollectedBenchStat::from_stat(
+            end - start,
+            end_time.duration_since(start_time),
+        ))
+    }
+
+    /// The current phase of the run
+    #[inline]
+    pub fn phase(&self) -> BenchPhase {
+        BenchPhase::from_u8(self.phase.load(Ordering::Acquire))
+    }
+
+    /// Check if the run is in the measured window, workers can use it to skip recording outside the window
+    #[inline]
+    pub fn measuring(&self) -> bool {
+        self.phase() == BenchPhase::Measure
+    }
+
+    /// The setup barrier: a worker calls it once it has connected to the peers,
+    /// and it returns when all the workers have called it (i.e., when the warmup starts),
+    /// or when the runner is stopped.
+    ///
+    /// The phases start after the barrier, so that a slow setup (e.g., connecting the QPs)
+    /// is neither measured nor eats into the warmup of the workers that are ready.
+    pub fn setup_done(&self) {
+        self.num_ready.fetch_add(1, Ordering::SeqCst);
+        // the warmup usually starts right after the last worker is ready, so spin for a while first
+        for _ in 0..SETUP_SPINS {
+            if !self.in_setup() {
+                return;
+            }
+            std::thread::yield_now();
+        }
+        let mut guard = self.setup_lock.lock().unwrap();
+        while self.in_setup() {
+            guard = self.setup_cond.wait(guard).unwrap();
+        }
+    }
+
+    fn in_setup(&self) -> bool {
+        self.phase() == BenchPhase::Setup && self.running()
+    }
+
+    /// Wake up the workers sleeping at the setup barrier
+    fn wake_setup(&self) {
+        // the lock orders the wakeup after the check of a worker going to sleep
+        let _guard = self.setup_lock.lock().unwrap();
+        self.setup_cond.notify_all();
+    }
+
+    /// Stop the workers without waiting for them
+    fn halt(&self) {
+        self.running.store(false, Ordering::SeqCst);
+        self.wake_setup();
+    }
+
+    /// Drive the phases of a runner created by [`BenchRunner::new_with_phases`],
+    /// and yield once per `interval`, see [`PhaseTicker`]
+    pub fn phase_ticker(&self, interval: Duration) -> PhaseTicker<'_, T> {
+        PhaseTicker::new(self, interval)
+    }
+
+    pub(crate) fn phases(&self) -> BenchPhases {
+        self.phases
+    }
+
+    /// Wait until all the workers reach the setup barrier,
+    /// or any of them exits (e.g., fails to connect) before reaching it.
+    ///
+    /// The `phase_ticker` waits for it before the warmup,
+    /// call it earlier to know that the workers are ready, e.g., before joining a controlled run.
+    pub fn wait_setup(&self) {
+        while self.running() && self.num_ready.load(Ordering::SeqCst) < self.num_workers {
+            if self.handlers.lock().unwrap().iter().any(|h| h.is_finished()) {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    pub(crate) fn enter_phase(&self, phase: BenchPhase) {
+        match phase {
+            BenchPhase::Measure => self.mark_measured(true),
+            BenchPhase::Cooldown => self.mark_measured(false),
+            _ => {}
+        }
+        self.phase.store(phase.as_u8(), Ordering::Release);
+        self.wake_setup();
+    }
+
+    /// Record the stats at the start (or the end) of the measured window
+    fn mark_measured(&self, start: bool) {
+        let mut measured = self.measured.lock().unwrap();
+        let slot = if start {
+            &mut measured.start
+        } else {
+            &mut measured.end
+        };
+        if slot.is_none() {
+            *slot = Some((Instant::now(), self.merged_stat()));
+        }
+    }
+
+    fn merged_stat(&self) -> BenchStat {
+        let mut res = BenchStat::default();
+        for stat in self.worker_stats() {
+            res.merge(&stat);
+        }
+        res
     }
 
     /// Check if the runner is still running
@@ -156,7 +670,7 @@ impl<T> BenchRunner<T> {
 mod tests {
     #[test]
     fn test_simple_runner() {
-        let mut runner = super::BenchRunner::new(2);
+        let runner = super::BenchRunner::new(2);
         runner.run(
             |_, r, _, _| {
                 while r.running() {
@@ -170,7 +684,7 @@ mod tests {
 
     #[test]
     fn test_runner_stop() {
-        let mut runner = super::BenchRunner::new(2);
+        let runner = super::BenchRunner::new(2);
         runner.run(
             |_, r, _, _| {
                 println!("Hello world");
@@ -186,7 +700,7 @@ mod tests {
 
     #[test]
     fn test_runner_input_work() {
-        let mut runner = super::BenchRunner::new(2);
+        let runner = super::BenchRunner::new(2);
         let input: usize = 73;
 
         runner.run(
@@ -204,8 +718,9 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::identity_op)]
     fn test_runner_output_work() {
-        let mut runner = super::BenchRunner::new(10);
+        let runner = super::BenchRunner::new(10);
         let input: usize = 73;
 
         runner.run(
@@ -228,4 +743,560 @@ mod tests {
         }
         assert_eq!(sum, 0 + 1 + 2 + 3 + 4 + 5 + 6 + 7 + 8 + 9);
     }
+
+    #[test]
+    fn test_runner_metrics() {
+        use crate::{CollectedMetric, SimpleBenchReporter};
+
+        let runner = super::BenchRunner::new(2);
+        let empty_polls = runner.register_counter("empty_polls");
+        let inflight = runner.register_gauge("inflight");
+        assert_eq!(runner.register_counter("empty_polls"), empty_polls);
+
+        let mut reporter = SimpleBenchReporter::new();
+        runner.run(
+            move |_, _, mut stat, _| {
+                stat.add_counter(empty_polls, 10);
+                stat.set_gauge(inflight, 4.0);
+            },
+            (),
+        );
+        // the workers have exited after stop, so all their updates are visible
+        runner.stop().unwrap();
+        let stat = runner.report(&mut reporter);
+
+        match stat.metrics["empty_polls"] {
+            CollectedMetric::Counter { total, .. } => assert_eq!(total, 20),
+            _ => panic!("empty_polls should be a counter"),
+        }
+        assert_eq!(stat.metrics["inflight"], CollectedMetric::Gauge(8.0));
+    }
+
+    #[test]
+    fn test_runner_bandwidth() {
+        use crate::SimpleBenchReporter;
+
+        let runner = super::BenchRunner::new(2);
+        let mut reporter = SimpleBenchReporter::new();
+        runner.run(
+            |_, _, mut stat, payload: u64| {
+                stat.finished_batch_ops(64);
+                stat.transferred_bytes(64 * payload);
+            },
+            1024,
+        );
+        runner.stop().unwrap();
+        let stat = runner.report(&mut reporter);
+
+        assert_eq!(stat.num_ops, 128);
+        assert_eq!(stat.num_bytes, 128 * 1024);
+        // bytes * 8 / µs / 1000 = Gbps
+        let expected = stat.throughput * 1024.0 * 8.0 / 1000.0;
+        assert!((stat.bandwidth - expected).abs() < 1e-9 * expected.max(1.0));
+    }
+
+    #[test]
+    fn test_runner_phases() {
+        use crate::{BenchPhase, BenchPhases, SimpleBenchReporter};
+        use std::time::Duration;
+
+        let runner = super::BenchRunner::new_with_phases(
+            2,
+            BenchPhases::new(
+                Duration::from_millis(30),
+                Duration::from_millis(60),
+                Duration::from_millis(30),
+            ),
+        );
+        assert_eq!(runner.phase(), BenchPhase::Setup);
+        runner.run(
+            |thread_id, r, mut stat, _| {
+                // the setup of one worker is slower
+                std::thread::sleep(Duration::from_millis(20 * thread_id as u64));
+                r.setup_done();
+                // no worker passes the barrier before all are connected
+                assert_ne!(r.phase(), BenchPhase::Setup);
+                while r.running() {
+                    stat.finished_one_op();
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            },
+            (),
+        );
+
+        let mut reporter = SimpleBenchReporter::new();
+        let mut phases = Vec::new();
+        for phase in runner.phase_ticker(Duration::from_millis(10)) {
+            assert_eq!(runner.report(&mut reporter).phase, phase);
+            phases.push(phase);
+        }
+        let measured = runner.measured_stat().unwrap();
+        runner.stop().unwrap();
+
+        assert_eq!(phases.first(), Some(&BenchPhase::Warmup));
+        assert_eq!(phases.last(), Some(&BenchPhase::Cooldown));
+        assert!(phases.contains(&BenchPhase::Measure));
+        assert!(phases.windows(2).all(|w| w[0].as_u8() <= w[1].as_u8()));
+
+        let total: u64 = runner.worker_stats().iter().map(|s| s.num_ops_finished).sum();
+        assert!(measured.num_ops > 0);
+        assert!(measured.num_ops < total);
+        // the window has ended, so it is not affected by the cooldown
+        assert_eq!(runner.measured_stat().unwrap().num_ops, measured.num_ops);
+    }
+
+    #[test]
+    fn test_runner_summary() {
+        use crate::SimpleBenchReporter;
+
+        let runner = super::BenchRunner::new(2);
+        let mut reporter = SimpleBenchReporter::new();
+        runner.run(
+            |_, _, mut stat, _| {
+                for i in 0..100 {
+                    stat.finished_one_op();
+                    stat.transferred_bytes(64);
+                    stat.record_latency_ns(1000 * (i % 10 + 1));
+                }
+            },
+            (),
+        );
+        runner.stop().unwrap();
+        runner.report(&mut reporter);
+        runner.report(&mut reporter);
+
+        let summary = runner.summary();
+        assert_eq!(summary.num_intervals, 2);
+        assert_eq!(summary.num_ops, 200);
+        assert_eq!(summary.num_bytes, 200 * 64);
+        assert_eq!(summary.max_latency, 10.0);
+        assert!(summary.p50_latency > 4.0 && summary.p50_latency < 6.0);
+        assert!(summary.duration > std::time::Duration::ZERO);
+        assert_eq!(summary.throughput.min, 0.0);
+        assert!(summary.throughput.max > 0.0);
+    }
+
+    #[test]
+    fn test_runner_report_async() {
+        use crate::{
+            AsyncBenchReporter, BenchPhase, BenchPhases, BenchReporter, BenchStat,
+            CollectedBenchStat, ReportFuture, SimpleBenchReporter,
+        };
+        use std::time::Duration;
+        use tokio::sync::mpsc;
+
+        /// A reporter pushing the reports to a channel, like a network reporter would
+        struct ChannelReporter {
+            inner: SimpleBenchReporter,
+            sender: mpsc::Sender<CollectedBenchStat>,
+        }
+
+        impl BenchReporter for ChannelReporter {
+            fn set_phase(&mut self, phase: BenchPhase) {
+                self.inner.set_phase(phase);
+            }
+
+            fn report_collected_stat(&mut self, stats: &[BenchStat]) -> CollectedBenchStat {
+                self.inner.report_collected_stat(stats)
+            }
+        }
+
+        impl AsyncBenchReporter for ChannelReporter {
+            fn async_report_collect_stat<'a>(
+                &'a mut self,
+                stats: &'a [BenchStat],
+            ) -> ReportFuture<'a> {
+                Box::pin(async move {
+                    let res = self.inner.report_collected_stat(stats);
+                    self.sender.send(res.clone()).await.unwrap();
+                    res
+                })
+            }
+        }
+
+        let runner = super::BenchRunner::new_with_phases(
+            1,
+            BenchPhases::new(Duration::ZERO, Duration::from_secs(60), Duration::ZERO),
+        );
+        runner.run(
+            |_, runner, mut stat, _| {
+                stat.finished_batch_ops(10);
+                runner.setup_done();
+            },
+            (),
+        );
+        runner.wait_setup();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (sender, mut receiver) = mpsc::channel(4);
+        rt.block_on(async {
+            // as a trait object
+            let mut reporters: Vec<Box<dyn AsyncBenchReporter>> = vec![Box::new(ChannelReporter {
+                inner: SimpleBenchReporter::new_with_id(3),
+                sender,
+            })];
+            let res = runner.report_async(&mut reporters[0]).await;
+            assert_eq!(res.num_ops, 10);
+            // the phase of the runner is stamped
+            assert_eq!(res.phase, BenchPhase::Setup);
+
+            let pushed = receiver.recv().await.unwrap();
+            assert_eq!(pushed.id, 3);
+            assert_eq!(pushed.num_ops, 10);
+        });
+        runner.stop().unwrap();
+    }
+
+    #[test]
+    fn test_runner_worker_breakdown() {
+        use crate::SimpleBenchReporter;
+        use std::time::Duration;
+
+        let runner = super::BenchRunner::new(3);
+        runner.run(
+            |thread_id, r, mut stat, _| {
+                while r.running() {
+                    // worker 2 is starved
+                    if thread_id != 2 {
+                        stat.finished_one_op();
+                    }
+                    std::thread::sleep(Duration::from_micros(100));
+                }
+            },
+            (),
+        );
+
+        let mut reporter = SimpleBenchReporter::new().with_worker_breakdown(0.5);
+        for _ in 0..2 {
+            std::thread::sleep(Duration::from_millis(20));
+            let res = runner.report(&mut reporter);
+            assert_eq!(res.workers.len(), 3);
+            assert_eq!(res.workers.iter().map(|w| w.num_ops).sum::<u64>(), res.num_ops);
+            let shares: f64 = res.workers.iter().map(|w| w.share).sum();
+            assert!((shares - 1.0).abs() < 1e-9);
+            assert_eq!(res.workers[2].throughput, 0.0);
+            assert!(res.worker_spread().unwrap() > 1.0);
+        }
+        runner.stop().unwrap();
+
+        // no breakdown by default
+        assert!(runner.report(&mut SimpleBenchReporter::new()).workers.is_empty());
+    }
+
+    #[test]
+    fn test_runner_pin_workers() {
+        use crate::{CorePlacement, SimpleBenchReporter};
+
+        let runner = super::BenchRunner::new(2);
+        assert_eq!(runner.core_of(0), None);
+        runner.pin_workers(CorePlacement::Compact).unwrap();
+        let cores: Vec<usize> = (0..2).map(|i| runner.core_of(i).unwrap()).collect();
+        // an unsatisfiable placement is rejected
+        assert!(runner.pin_workers(CorePlacement::NumaNode(usize::MAX)).is_err());
+
+        runner.run(
+            |thread_id, r, mut stat, _| {
+                stat.finished_one_op();
+                r.core_of(thread_id).unwrap()
+            },
+            (),
+        );
+        let mut reporter = SimpleBenchReporter::new().with_worker_breakdown(f64::INFINITY);
+        let res = runner.report(&mut reporter);
+        // the cores are reported along with the workers
+        assert_eq!(res.workers.iter().map(|w| w.core.unwrap()).collect::<Vec<_>>(), cores);
+
+        let mut res = runner.stop().unwrap();
+        res.sort();
+        let mut expected = cores.clone();
+        expected.sort();
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn test_runner_open_loop() {
+        use crate::{Arrival, Clock, OpenLoop, SimpleBenchReporter};
+        use std::time::{Duration, Instant};
+
+        let runner = super::BenchRunner::new(2);
+        assert!(runner.rate_limiter(0).is_none());
+        // 2K ops/s in total
+        runner.set_open_loop(OpenLoop::new(2e3, Arrival::Constant));
+        let begin = Instant::now();
+        runner.run(
+            |thread_id, r, mut stat, _| {
+                let clock = Clock::get();
+                let mut limiter = r.rate_limiter(thread_id).unwrap();
+                while r.running() {
+                    let intended = limiter.wait();
+                    stat.finished_one_op();
+                    stat.record_latency_ns(clock.elapsed_ns(intended));
+                }
+            },
+            (),
+        );
+        let mut reporter = SimpleBenchReporter::new();
+        std::thread::sleep(Duration::from_millis(100));
+        runner.stop().unwrap();
+        let res = runner.report(&mut reporter);
+
+        // each worker sends one op per ms
+        let expected = begin.elapsed().as_millis() as u64 * 2;
+        assert!(res.num_ops <= expected, "{} ops, expect {}", res.num_ops, expected);
+        assert!(res.num_ops >= 150, "{} ops, expect {}", res.num_ops, expected);
+        assert_eq!(res.latency.count(), res.num_ops);
+    }
+
+    #[test]
+    fn test_runner_bounded_by_ops() {
+        use crate::RunBound;
+        use std::time::{Duration, Instant};
+
+        let runner = super::BenchRunner::new(2);
+        runner.set_bound(RunBound::Ops(1000));
+        runner.run(
+            |_, r, mut stat, _| {
+                while r.running() {
+                    stat.finished_one_op();
+                    std::thread::sleep(Duration::from_micros(10));
+                }
+            },
+            (),
+        );
+        // the workers are stopped by the bound
+        let begin = Instant::now();
+        while runner.running() {
+            assert!(begin.elapsed() < Duration::from_secs(10), "the bound is never reached");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(runner.bound_reached());
+        runner.stop().unwrap();
+
+        let measured = runner.measured_stat().unwrap();
+        assert!(measured.num_ops >= 1000);
+        // the ops after the bound are excluded
+        assert!(measured.num_ops < 1000 + 1000, "{} ops", measured.num_ops);
+        assert_eq!(runner.summary().duration, measured.duration);
+    }
+
+    #[test]
+    fn test_runner_bounded_by_bytes_with_cooldown() {
+        use crate::{BenchPhase, BenchPhases, RunBound, SimpleBenchReporter};
+        use std::time::{Duration, Instant};
+
+        let runner = super::BenchRunner::new_with_phases(
+            1,
+            BenchPhases::new(
+                Duration::from_millis(10),
+                Duration::from_secs(60),
+                Duration::from_millis(20),
+            ),
+        );
+        runner.set_bound(RunBound::Bytes(64 * 1000));
+        runner.run(
+            |_, r, mut stat, _| {
+                r.setup_done();
+                while r.running() {
+                    if r.measuring() {
+                        stat.finished_one_op();
+                        stat.transferred_bytes(64);
+                    }
+                    std::thread::sleep(Duration::from_micros(10));
+                }
+            },
+            (),
+        );
+
+        let begin = Instant::now();
+        let mut reporter = SimpleBenchReporter::new();
+        let mut phases = Vec::new();
+        for phase in runner.phase_ticker(Duration::from_secs(1)) {
+            runner.report(&mut reporter);
+            phases.push(phase);
+        }
+        // the measured window ends long before its duration, then the cooldown runs
+        assert!(begin.elapsed() < Duration::from_secs(30));
+        assert!(runner.bound_reached());
+        assert_eq!(phases.last(), Some(&BenchPhase::Cooldown));
+        assert!(phases.contains(&BenchPhase::Measure));
+        assert!(runner.running());
+        runner.stop().unwrap();
+
+        let measured = runner.measured_stat().unwrap();
+        assert!(measured.num_bytes >= 64 * 1000);
+        assert!(measured.duration < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_runner_worker_failure() {
+        use crate::{BenchPhases, SimpleBenchReporter};
+        use std::time::{Duration, Instant};
+
+        let runner = super::BenchRunner::new_with_phases(
+            3,
+            BenchPhases::new(Duration::ZERO, Duration::from_secs(60), Duration::ZERO),
+        );
+        runner.run(
+            |thread_id, r, mut stat, _| {
+                r.setup_done();
+                let mut n = 0;
+                while r.running() {
+                    stat.finished_one_op();
+                    n += 1;
+                    assert!(thread_id != 1 || n < 100, "bad completion");
+                    std::thread::sleep(Duration::from_micros(10));
+                }
+                n
+            },
+            (),
+        );
+
+        // the failure stops the other workers and the ticker long before the measured window ends
+        let begin = Instant::now();
+        let mut reporter = SimpleBenchReporter::new();
+        for _ in runner.phase_ticker(Duration::from_millis(10)) {
+            runner.report(&mut reporter);
+        }
+        assert!(begin.elapsed() < Duration::from_secs(30));
+        assert!(!runner.running());
+        assert!(runner.failed());
+
+        let err = runner.stop().unwrap_err();
+        assert_eq!(err.failures.len(), 1);
+        assert_eq!(err.first().worker, 1);
+        assert_eq!(err.first().message, "bad completion");
+        assert_eq!(runner.failures(), err.failures);
+    }
+
+    #[test]
+    fn test_runner_per_worker_inputs() {
+        use crate::LatencyHistogram;
+
+        // the inputs are moved to the workers, and need not be cloned
+        struct Endpoint {
+            latency_ns: u64,
+        }
+
+        let runner = super::BenchRunner::new(3);
+        runner.run_with_inputs(
+            |thread_id, r, _, ep: Endpoint| {
+                while r.running() {
+                    std::thread::yield_now();
+                }
+                assert_eq!(ep.latency_ns, 1000 * (thread_id as u64 + 1));
+                // an owned, non-Copy result
+                let mut hist = LatencyHistogram::new();
+                hist.record_n(ep.latency_ns, 10);
+                hist
+            },
+            (1..=3).map(|i| Endpoint { latency_ns: 1000 * i }).collect(),
+        );
+
+        let hist = runner
+            .stop_and_reduce(|mut a, b| {
+                a.merge(&b);
+                a
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(hist.count(), 30);
+        assert_eq!(hist.mean(), 2000.0);
+    }
+
+    #[test]
+    fn test_runner_input_factory() {
+        let runner = super::BenchRunner::new(4);
+        runner.run_with_factory(
+            |thread_id, r, _, name: String| {
+                while r.running() {
+                    std::thread::yield_now();
+                }
+                assert_eq!(name, format!("qp-{}", thread_id));
+                name
+            },
+            |thread_id| format!("qp-{}", thread_id),
+        );
+
+        // the results are in the order of the workers
+        assert_eq!(runner.stop().unwrap(), ["qp-0", "qp-1", "qp-2", "qp-3"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "expect one input for each worker")]
+    fn test_runner_missing_inputs() {
+        let runner = super::BenchRunner::new(2);
+        runner.run_with_inputs(|_, _, _, _: usize| {}, vec![0]);
+    }
+
+    #[test]
+    fn test_runner_stop_in_setup() {
+        use crate::BenchPhases;
+        use std::time::{Duration, Instant};
+
+        let runner = super::BenchRunner::new_with_phases(
+            4,
+            BenchPhases::new(Duration::ZERO, Duration::from_secs(60), Duration::ZERO),
+        );
+        runner.run(
+            |_, r, _, _| {
+                r.setup_done();
+                r.running()
+            },
+            (),
+        );
+        // the workers sleep at the setup barrier, since no ticker starts the warmup
+        runner.wait_setup();
+        std::thread::sleep(Duration::from_millis(50));
+
+        let begin = Instant::now();
+        assert_eq!(runner.stop().unwrap(), vec![false; 4]);
+        assert!(begin.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_runner_stop_on_shutdown() {
+        use crate::{BenchPhases, Shutdown, SimpleBenchReporter};
+        use std::time::{Duration, Instant};
+
+        let shutdown = Shutdown::new();
+        let runner = super::BenchRunner::new_with_phases(
+            2,
+            BenchPhases::new(Duration::ZERO, Duration::from_secs(60), Duration::ZERO),
+        );
+        runner.stop_on(&shutdown);
+        runner.run(
+            |_, r, mut stat, _| {
+                r.setup_done();
+                while r.running() {
+                    stat.finished_one_op();
+                    std::thread::sleep(Duration::from_micros(10));
+                }
+            },
+            (),
+        );
+
+        let trigger = shutdown.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            trigger.trigger();
+        });
+
+        let begin = Instant::now();
+        let mut reporter = SimpleBenchReporter::new();
+        let mut last = None;
+        for _ in runner.phase_ticker(Duration::from_millis(10)) {
+            last = Some(runner.report(&mut reporter));
+        }
+        handle.join().unwrap();
+        assert!(begin.elapsed() < Duration::from_secs(30));
+        assert!(runner.interrupted());
+        assert!(!runner.failed());
+        assert!(last.is_some());
+        runner.stop().unwrap();
+
+        // the summary covers the measured window up to the shutdown
+        let summary = runner.summary();
+        assert!(summary.duration < Duration::from_secs(30));
+        assert!(runner.measured_stat().unwrap().num_ops > 0);
+    }
 }
@@ -0,0 +1,218 @@
+//! The phases of a benchmark run.
+//!
+//! A run configured with [`BenchPhases`] (see [`crate::BenchRunner::new_with_phases`]) goes through:
+//! 1. [`BenchPhase::Setup`]: workers create their QPs, handshake and register MRs,
+//!    then reach the setup barrier ([`crate::BenchRunner::setup_done`]).
+//! 2. [`BenchPhase::Warmup`]: workers run, but the results are excluded.
+//! 3. [`BenchPhase::Measure`]: the measured window.
+//! 4. [`BenchPhase::Cooldown`]: workers keep running, so that the peers are not disturbed
+//!    when the measured window ends, but the results are excluded.
+//!
+//! The phases are driven by the [`PhaseTicker`] at the reporting thread, e.g.,
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use netbencher_core::{BenchPhases, BenchRunner, SimpleBenchReporter};
+//!
+//! let runner = BenchRunner::new_with_phases(
+//!     4,
+//!     BenchPhases::new(Duration::from_secs(2), Duration::from_secs(10), Duration::from_secs(1)),
+//! );
+//! runner.run(|_, runner, mut stat, _| {
+//!     // create the QPs ...
+//!     runner.setup_done();
+//!     while runner.running() {
+//!         stat.finished_one_op();
+//!     }
+//! }, ());
+//!
+//! let mut reporter = SimpleBenchReporter::new();
+//! for _phase in runner.phase_ticker(Duration::from_secs(1)) {
+//!     println!("{}", runner.report(&mut reporter));
+//! }
+//! runner.stop().unwrap();
+//! ```
+use std::time::{Duration, Instant};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{BenchRunner, BenchStat};
+
+/// How often a bounded run checks its bound
+pub(crate) const BOUND_CHECK_INTERVAL: Duration = Duration::from_micros(100);
+
+/// How many times a worker yields at the setup barrier before it sleeps until the warmup
+pub(crate) const SETUP_SPINS: usize = 1000;
+
+/// The phase of a benchmark run.
+///
+/// A run without explicit phases is always at [`BenchPhase::Measure`], which is thus the default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BenchPhase {
+    /// Workers are connecting to the peers
+    Setup,
+    /// Workers are running, but the results are excluded
+    Warmup,
+    /// The measured window
+    #[default]
+    Measure,
+    /// The measured window has ended, but the workers are still running
+    Cooldown,
+}
+
+impl BenchPhase {
+    pub(crate) fn from_u8(v: u8) -> Self {
+        match v {
+            0 => BenchPhase::Setup,
+            1 => BenchPhase::Warmup,
+            2 => BenchPhase::Measure,
+            _ => BenchPhase::Cooldown,
+        }
+    }
+
+    pub(crate) fn as_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+impl std::fmt::Display for BenchPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            BenchPhase::Setup => "setup",
+            BenchPhase::Warmup => "warmup",
+            BenchPhase::Measure => "measure",
+            BenchPhase::Cooldown => "cooldown",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The durations of the timed phases of a run
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BenchPhases {
+    /// How long to warm up before measuring
+    pub warmup: Duration,
+    /// How long to measure
+    pub measure: Duration,
+    /// How long to keep the workers running after measuring
+    pub cooldown: Duration,
+}
+
+impl BenchPhases {
+    /// Create the phases config
+    pub fn new(warmup: Duration, measure: Duration, cooldown: Duration) -> Self {
+        Self {
+            warmup,
+            measure,
+            cooldown,
+        }
+    }
+}
+
+/// A bound of the measured window besides its duration, see [`BenchRunner::set_bound`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunBound {
+    /// End the measured window once the workers finish this number of ops in total
+    Ops(u64),
+    /// End the measured window once the workers transfer this number of bytes in total
+    Bytes(u64),
+}
+
+impl RunBound {
+    /// Whether the bound is reached by the ops and bytes finished in the measured window
+    pub(crate) fn reached(&self, num_ops: u64, num_bytes: u64) -> bool {
+        match *self {
+            RunBound::Ops(n) => num_ops >= n,
+            RunBound::Bytes(n) => num_bytes >= n,
+        }
+    }
+}
+
+/// An iterator that drives the phases of a [`BenchRunner`].
+///
+/// Each `next` first waits for the setup barrier if needed, then sleeps for one report interval
+/// (or until the end of the current phase, so that no interval straddles two phases),
+/// and returns the phase of the interval. It ends when the cooldown ends.
+/// The workers are not stopped by the ticker, call [`BenchRunner::stop`] afterwards.
+///
+/// If the run is bounded (see [`BenchRunner::set_bound`]), the measured window also ends once the bound is reached,
+/// and the interval returns early.
+pub struct PhaseTicker<'a, T> {
+    runner: &'a BenchRunner<T>,
+    interval: Duration,
+    phase_end: Instant,
+}
+
+impl<'a, T> PhaseTicker<'a, T> {
+    pub(crate) fn new(runner: &'a BenchRunner<T>, interval: Duration) -> Self {
+        Self {
+            runner,
+            interval,
+            phase_end: Instant::now(),
+        }
+    }
+
+    fn phases(&self) -> BenchPhases {
+        self.runner.phases()
+    }
+
+    /// Move to the next phase, return false if all the phases are done
+    fn advance(&mut self) -> bool {
+        let (next, duration) = match self.runner.phase() {
+            BenchPhase::Setup => {
+                self.runner.wait_setup();
+                (BenchPhase::Warmup, self.phases().warmup)
+            }
+            BenchPhase::Warmup => (BenchPhase::Measure, self.phases().measure),
+            BenchPhase::Measure => (BenchPhase::Cooldown, self.phases().cooldown),
+            BenchPhase::Cooldown => return false,
+        };
+        self.runner.enter_phase(next);
+        self.phase_end = Instant::now() + duration;
+        true
+    }
+}
+
+impl<'a, T> Iterator for PhaseTicker<'a, T> {
+    type Item = BenchPhase;
+
+    fn next(&mut self) -> Option<BenchPhase> {
+        loop {
+            if !self.runner.running() {
+                return None;
+            }
+            let now = Instant::now();
+            if self.runner.phase() == BenchPhase::Setup || now >= self.phase_end {
+                if !self.advance() {
+                    return None;
+                }
+                continue;
+            }
+            let phase = self.runner.phase();
+            let tick = now + std::cmp::min(self.interval, self.phase_end - now);
+            if phase == BenchPhase::Measure && self.runner.bound().is_some() {
+                // wake up once the bound is reached, so that the measured window ends at once
+                while !self.runner.bound_reached() && self.runner.running() {
+                    let now = Instant::now();
+                    if now >= tick {
+                        break;
+                    }
+                    std::thread::sleep(std::cmp::min(BOUND_CHECK_INTERVAL, tick - now));
+                }
+                if self.runner.bound_reached() {
+                    self.phase_end = Instant::now();
+                }
+            } else {
+                std::thread::sleep(tick - now);
+            }
+            return Some(phase);
+        }
+    }
+}
+
+/// The merged stats of all the workers at the start and the end of the measured window
+#[derive(Default)]
+pub(crate) struct MeasuredWindow {
+    pub start: Option<(Instant, BenchStat)>,
+    pub end: Option<(Instant, BenchStat)>,
+}
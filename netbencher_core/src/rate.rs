@@ -0,0 +1,298 @@
+//! Open-loop load generation: the ops are sent at a target rate, instead of after the previous ops complete.
+//!
+//! A closed-loop worker waits for its ops before sending more, so a slow server also slows down the sending,
+//! and the queueing delay is never measured (i.e., the coordinated omission).
+//! In an open-loop run, each op has an intended send time drawn from a schedule ([`Arrival`]),
+//! and its latency is measured from the intended send time, so an op sent late still counts its wait.
+//! The send times are readings of the [`Clock`], so that timing an op stays as cheap as in a closed-loop run.
+//!
+//! The target rate of a runner is split evenly across its workers (see [`crate::BenchRunner::set_open_loop`]):
+//!
+//! ```no_run
+//! use netbencher_core::{Arrival, BenchRunner, Clock, OpenLoop};
+//!
+//! let runner = BenchRunner::new(4);
+//! // 1M ops/s in total, 250K ops/s per worker
+//! runner.set_open_loop(OpenLoop::new(1e6, Arrival::Poisson));
+//! runner.run(|thread_id, runner, mut stat, _| {
+//!     // connect to the server ...
+//!     runner.setup_done();
+//!     let clock = Clock::get();
+//!     let mut limiter = runner.rate_limiter(thread_id).unwrap();
+//!     while runner.running() {
+//!         let intended = limiter.wait();
+//!         // send the op, and wait for its completion ...
+//!         stat.finished_one_op();
+//!         stat.record_latency_ns(clock.elapsed_ns(intended));
+//!     }
+//! }, ());
+//! runner.stop().unwrap();
+//! ```
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::Clock;
+
+/// Sleep instead of spinning if the next op is due in more than this
+const SPIN_THRESHOLD: Duration = Duration::from_micros(200);
+
+/// An op handed out more than this after its intended send time is late
+const LATE_SLACK: Duration = Duration::from_micros(10);
+
+/// The schedule of the intended send times
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Arrival {
+    /// Exponentially distributed inter-arrival times, i.e., the ops arrive as a Poisson process
+    #[default]
+    Poisson,
+    /// The same inter-arrival time between any two ops
+    Constant,
+}
+
+impl FromStr for Arrival {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "poisson" => Ok(Self::Poisson),
+            "constant" => Ok(Self::Constant),
+            _ => Err(format!("unknown arrival {}, expect poisson or constant", s)),
+        }
+    }
+}
+
+/// The target rate of an open-loop run
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OpenLoop {
+    /// The target rate (ops per second) of all the workers of the runner
+    pub ops_per_sec: f64,
+    /// The schedule of the ops
+    pub arrival: Arrival,
+}
+
+impl OpenLoop {
+    /// Create an open-loop run with a target aggregate rate (ops per second)
+    pub fn new(ops_per_sec: f64, arrival: Arrival) -> Self {
+        assert!(ops_per_sec > 0.0, "the target rate must be positive");
+        Self {
+            ops_per_sec,
+            arrival,
+        }
+    }
+}
+
+/// Decide the intended send time of each op of a worker, as a reading of the [`Clock`]
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    clock: &'static Clock,
+    // the mean inter-arrival time
+    mean_gap_ns: f64,
+    arrival: Arrival,
+    // the schedule is kept in nanoseconds from the origin, so that the fractions of the gaps add up
+    origin: u64,
+    next_ns: f64,
+    // the intended send time of the next op, in ticks
+    next: u64,
+    // the number of ops scheduled so far
+    num_scheduled: u64,
+    // the state of the xorshift generator, never 0
+    rng: u64,
+    num_late: u64,
+}
+
+impl RateLimiter {
+    /// Create a limiter sending `ops_per_sec` ops per second from now, the seed decides the Poisson schedule
+    pub fn new(ops_per_sec: f64, arrival: Arrival, seed: u64) -> Self {
+        assert!(ops_per_sec > 0.0, "the target rate must be positive");
+        let clock = Clock::get();
+        let mut res = Self {
+            clock,
+            mean_gap_ns: 1e9 / ops_per_sec,
+            arrival,
+            origin: clock.now(),
+            next_ns: 0.0,
+            next: 0,
+            num_scheduled: 0,
+            // splitmix the seed, so that close seeds give unrelated schedules
+            rng: splitmix64(seed) | 1,
+            num_late: 0,
+        };
+        res.schedule_next();
+        res
+    }
+
+    /// The intended send time of the next op, in ticks of the [`Clock`]
+    #[inline]
+    pub fn next_send_time(&self) -> u64 {
+        self.next
+    }
+
+    /// If the next op is due, return its intended send time (in ticks) and schedule the one after it
+    #[inline]
+    pub fn poll(&mut self) -> Option<u64> {
+        let intended = self.next;
+        let now = self.clock.now();
+        if now < intended {
+            return None;
+        }
+        self.count_late(now - intended);
+        self.schedule_next();
+        Some(intended)
+    }
+
+    /// Wait until the next op is due, return its intended send time (in ticks) and schedule the one after it.
+    ///
+    /// If the worker is behind the schedule, it returns at once, and the ops keep their intended send times,
+    /// so that their latencies include the delay.
+    pub fn wait(&mut self) -> u64 {
+        let intended = self.next;
+        let now = self.clock.now();
+        if now > intended {
+            self.count_late(now - intended);
+        } else {
+            let remaining = Duration::from_nanos(self.clock.ticks_to_ns(intended - now));
+            if remaining > SPIN_THRESHOLD {
+                std::thread::sleep(remaining - SPIN_THRESHOLD / 2);
+            }
+            while self.clock.now() < intended {
+                std::hint::spin_loop();
+            }
+        }
+        self.schedule_next();
+        intended
+    }
+
+    /// Count an op handed out `behind` ticks after its intended send time
+    #[inline]
+    fn count_late(&mut self, behind: u64) {
+        if self.clock.ticks_to_ns(behind) > LATE_SLACK.as_nanos() as u64 {
+            self.num_late += 1;
+        }
+    }
+
+    /// The number of ops handed out (by [`RateLimiter::poll`] or [`RateLimiter::wait`])
+    /// more than a few microseconds after their intended send times,
+    /// i.e., the worker can not keep up with the target rate
+    pub fn num_late(&self) -> u64 {
+        self.num_late
+    }
+
+    fn schedule_next(&mut self) {
+        self.num_scheduled += 1;
+        self.next_ns = match self.arrival {
+            // from the origin, so that the rounding errors do not add up
+            Arrival::Constant => self.num_scheduled as f64 * self.mean_gap_ns,
+            Arrival::Poisson => self.next_ns + self.next_gap_ns(),
+        };
+        self.next = self.origin + self.clock.ns_to_ticks(self.next_ns as u64);
+    }
+
+    /// Draw the time (in nanoseconds) to the next op of a Poisson schedule
+    fn next_gap_ns(&mut self) -> f64 {
+        // uniform in (0, 1]
+        let u = ((self.next_random() >> 11) + 1) as f64 / (1u64 << 53) as f64;
+        -u.ln() * self.mean_gap_ns
+    }
+
+    /// xorshift64*
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Arrival, RateLimiter};
+    use crate::Clock;
+
+    #[test]
+    fn test_constant_schedule() {
+        let mut limiter = RateLimiter::new(1e3, Arrival::Constant, 0);
+        let first = limiter.next_send_time();
+        // not due yet
+        assert_eq!(limiter.poll(), None);
+
+        let clock = Clock::get();
+        let intended: Vec<u64> = (0..5).map(|_| limiter.wait()).collect();
+        assert_eq!(intended[0], first);
+        for w in intended.windows(2) {
+            // up to a tick lost to the rounding of each send time
+            let gap = clock.ticks_to_ns(w[1] - w[0]);
+            assert!(gap.abs_diff(1_000_000) <= 2, "{} ns", gap);
+        }
+        assert!(clock.now() >= intended[4]);
+    }
+
+    #[test]
+    fn test_poisson_schedule() {
+        let mut limiter = RateLimiter::new(1e6, Arrival::Poisson, 42);
+        let n = 100_000;
+        let gaps: Vec<f64> = (0..n).map(|_| limiter.next_gap_ns()).collect();
+        let mean = gaps.iter().sum::<f64>() / n as f64;
+        // the mean and the stddev of an exponential distribution are the same
+        let stddev = (gaps.iter().map(|g| (g - mean).powi(2)).sum::<f64>() / n as f64).sqrt();
+        assert!((mean - 1000.0).abs() < 20.0, "mean gap: {}", mean);
+        assert!((stddev - 1000.0).abs() < 50.0, "stddev of the gaps: {}", stddev);
+
+        // different seeds give different schedules
+        let mut a = RateLimiter::new(1e6, Arrival::Poisson, 0);
+        let mut b = RateLimiter::new(1e6, Arrival::Poisson, 1);
+        assert_ne!(
+            (0..4).map(|_| a.next_gap_ns()).collect::<Vec<_>>(),
+            (0..4).map(|_| b.next_gap_ns()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_constant_gaps_add_up() {
+        // 142.857 ns per op
+        let mut limiter = RateLimiter::new(7e6, Arrival::Constant, 0);
+        let first_ns = limiter.next_ns;
+        let first = limiter.next_send_time();
+        for _ in 0..1_000_000 {
+            limiter.schedule_next();
+        }
+        let expected = 1e6 * 1e9 / 7e6;
+        assert!((limiter.next_ns - first_ns - expected).abs() < 1e-3);
+        let elapsed = Clock::get().ticks_to_ns(limiter.next_send_time() - first) as f64;
+        assert!((elapsed - expected).abs() <= 2.0, "{} ns", elapsed);
+    }
+
+    #[test]
+    fn test_late_ops_keep_their_send_times() {
+        let clock = Clock::get();
+        let mut limiter = RateLimiter::new(1e3, Arrival::Constant, 0);
+        // the worker stalls for 5 ops
+        std::thread::sleep(Duration::from_millis(5));
+        let first = limiter.poll().unwrap();
+        let second = limiter.wait();
+        assert!(clock.ticks_to_ns(second - first).abs_diff(1_000_000) <= 2);
+        assert!(clock.elapsed(second) >= Duration::from_millis(2));
+        assert_eq!(limiter.num_late(), 2);
+
+        // the ops taken by poll only are late as well
+        let mut limiter = RateLimiter::new(1e3, Arrival::Constant, 0);
+        std::thread::sleep(Duration::from_millis(5));
+        while limiter.poll().is_some() {}
+        assert!(limiter.num_late() >= 4, "{} late ops", limiter.num_late());
+        // but not the ones on time
+        let mut limiter = RateLimiter::new(1e3, Arrival::Constant, 0);
+        limiter.wait();
+        assert_eq!(limiter.num_late(), 0);
+
+        assert_eq!("constant".parse(), Ok(Arrival::Constant));
+        assert!("bursty".parse::<Arrival>().is_err());
+    }
+}
@@ -0,0 +1,126 @@
+//! The per-worker breakdown of a report, so that the starved workers are visible,
+//! e.g., the threads mapped onto a second NIC, or the clients sharing a server thread.
+//!
+//! The imbalance of a set of throughputs is measured by their spread, i.e., `(max - min) / mean`:
+//! 0 if they are the same, and 1 if, e.g., one of two workers runs 3x faster than the other.
+use log::warn;
+use serde_derive::{Deserialize, Serialize};
+
+use super::CollectedBenchStat;
+
+/// The throughput of one worker during a period
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct WorkerThroughput {
+    /// The id of the worker, i.e., its thread id in the runner
+    pub worker: usize,
+    /// The core the worker is pinned to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core: Option<usize>,
+    /// The number of ops finished during a period
+    pub num_ops: u64,
+    /// The throughput (Mops/s) of the worker
+    pub throughput: f64,
+    /// The share of the worker in the throughput of all the workers
+    pub share: f64,
+}
+
+impl std::fmt::Display for WorkerThroughput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.worker)?;
+        if let Some(core) = self.core {
+            write!(f, " (core {})", core)?;
+        }
+        write!(
+            f,
+            ": {:.4} Mops/s ({:.1}%)",
+            self.throughput,
+            self.share * 100.0
+        )
+    }
+}
+
+/// The breakdown of the ops finished by each worker (pinned to a core, if any) during a period (in microseconds)
+pub(crate) fn breakdown_of(workers: &[(u64, Option<usize>)], duration_us: f64) -> Vec<WorkerThroughput> {
+    let total: u64 = workers.iter().map(|(n, _)| n).sum();
+    workers
+        .iter()
+        .enumerate()
+        .map(|(worker, &(n, core))| WorkerThroughput {
+            worker,
+            core,
+            num_ops: n,
+            throughput: n as f64 / duration_us,
+            share: if total == 0 {
+                0.0
+            } else {
+                n as f64 / total as f64
+            },
+        })
+        .collect()
+}
+
+/// The spread `(max - min) / mean` of the throughputs,
+/// or `None` if there are fewer than two of them or nothing is finished
+pub(crate) fn spread<I: IntoIterator<Item = f64>>(throughputs: I) -> Option<f64> {
+    let (mut min, mut max, mut sum, mut n) = (f64::MAX, 0.0f64, 0.0, 0);
+    for t in throughputs {
+        min = min.min(t);
+        max = max.max(t);
+        sum += t;
+        n += 1;
+    }
+    if n < 2 || sum <= 0.0 {
+        return None;
+    }
+    Some((max - min) / (sum / n as f64))
+}
+
+/// Warn about a report whose workers are imbalanced by more than `max_spread`
+pub(crate) fn check_workers(stat: &CollectedBenchStat, max_spread: f64) {
+    if let Some(s) = stat.worker_spread() {
+        if s > max_spread {
+            let slowest = stat
+                .workers
+                .iter()
+                .min_by(|a, b| a.throughput.total_cmp(&b.throughput))
+                .unwrap();
+            warn!(
+                "the workers of reporter {} are imbalanced, spread: {:.2}, slowest worker {}",
+                stat.id, s, slowest
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{breakdown_of, spread};
+
+    #[test]
+    fn test_breakdown() {
+        let workers = breakdown_of(&[(300, None), (100, Some(3)), (0, None)], 1e6);
+        assert_eq!(workers.len(), 3);
+        assert_eq!(workers[1].worker, 1);
+        assert_eq!(workers[0].throughput, 300.0 / 1e6);
+        assert_eq!(workers[0].share, 0.75);
+        assert_eq!(workers[2].share, 0.0);
+        assert_eq!(workers[0].to_string(), "#0: 0.0003 Mops/s (75.0%)");
+        assert_eq!(workers[1].to_string(), "#1 (core 3): 0.0001 Mops/s (25.0%)");
+
+        // nothing finished
+        assert!(breakdown_of(&[(0, None), (0, None)], 1e6).iter().all(|w| w.share == 0.0));
+    }
+
+    #[test]
+    fn test_spread() {
+        assert_eq!(spread([1.0, 1.0, 1.0]), Some(0.0));
+        // one worker is 3x faster
+        assert_eq!(spread([1.0, 3.0]), Some(1.0));
+        // a starved worker
+        assert_eq!(spread([2.0, 2.0, 0.0, 2.0]), Some(2.0 / 1.5));
+
+        assert_eq!(spread([5.0]), None);
+        assert_eq!(spread([0.0, 0.0]), None);
+        assert_eq!(spread(Vec::new()), None);
+    }
+}
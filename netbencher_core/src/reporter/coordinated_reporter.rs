@@ -1,6 +1,7 @@
 //! A reporter that reports the throughput and latency of workers from this machine.
 //! This contains two parts: a master and several reporters.
-//! The master collects the reports from the reporters and aggregates them accordingly, see `report_event_loop`.
+//! The master collects the reports from the reporters and aggregates them accordingly, see `report_event_loop`
+//! (or `report_event_loop_until` to stop it with a cancellation signal).
 //!
 //! To use this module, first start the master at one node, then start the reporters at other nodes.
 //! More specifically, see the following master example:
@@ -29,114 +30,705 @@
 //! let inner_reporter = SimpleBenchReporter::new_with_id(0); // can be any reporter
 //! let mut reporter = CoordinatedReporter::new(
 //!     "127.0.0.1:8888", inner_reporter).await.expect("failed to create the reporter");
+//! // optional, let the master assign (with None) or validate the id
+//! reporter.register(Some(0)).await.expect("failed to register at the master");
 //!
 //! // send a report to the master
-//! bench.async_report(&mut reporter).await;
+//! bench.report_async(&mut reporter).await;
 //!
+//! // tell the master that the reporter has finished
+//! reporter.finish().await.expect("failed to send the final report");
 //! ```
 //!
+//! The reports are sequenced and may span several datagrams (see the `transport` module),
+//! so the master counts the lost reports of each reporter, and logs them at each interval and at the end.
+//!
 //! More example can be found at `netbencher-core/examples/coordinator_report_worker.rs` and `netbencher-core/examples/coordinator_report_master.rs`.
 //!
+use std::collections::{BTreeMap, VecDeque};
 use std::net::SocketAddr;
-use std::sync::Arc;
 use std::time::Duration;
-use std::time::Instant;
 
 use tokio::net::UdpSocket;
+use tokio::time::MissedTickBehavior;
+
+use crate::{AsyncBenchReporter, BenchPhase};
+
+use super::transport::{
+    encode_fragments, MasterMessage, Reassembler, ReportBody, ReportMessage, SeqStatus, SeqTracker,
+};
+use super::{
+    check_workers, spread, unix_time_ms, unix_time_us, BenchReporter, BenchStat, BenchSummary,
+    CollectedBenchStat, PrometheusExporter, ReportFuture, StatFile,
+};
 
-use crate::AsyncBenchReporter;
+use log::{info, warn};
 
-use super::{BenchReporter, BenchStat, CollectedBenchStat};
+/// The maximum size of a datagram, reports larger than a datagram are fragmented (see the `transport` module)
+const MAX_DATAGRAM_SZ: usize = 65536;
 
-use log::{info};
+/// How long a reporter waits for the reply of the master to each join request or final report
+const REGISTER_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How many times a reporter sends its join request or final report before giving up
+const REGISTER_RETRIES: usize = 10;
+
+/// How many pings a reporter sends to estimate the clock offset to the master
+const CLOCK_SYNC_PINGS: usize = 8;
+
+/// The state of a reporter known by the master
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MemberState {
+    /// The reporter is sending reports
+    Active,
+    /// The reporter has sent its final report
+    Finished,
+    /// No message is received from the reporter for a while
+    TimedOut,
+}
+
+/// The state of the run driven by the event loop of the master
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RunState {
+    /// Waiting for the reporters of a controlled run to join
+    Waiting,
+    /// Collecting the reports
+    Running,
+    /// STOP is broadcast, waiting for the final reports
+    Stopping,
+}
+
+impl std::fmt::Display for MemberState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MemberState::Active => write!(f, "active"),
+            MemberState::Finished => write!(f, "finished"),
+            MemberState::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+/// A reporter that has joined the master
+struct Member {
+    addr: SocketAddr,
+    state: MemberState,
+    // the recent reports, ordered by their end time
+    recent_reports: VecDeque<CollectedBenchStat>,
+    // the accumulated reports in the measured window
+    measured: CollectedBenchStat,
+    // the sequence numbers received
+    seq: SeqTracker,
+    // when the last message is received
+    last_seen_ms: u64,
+    // whether a report is received since the run started
+    started: bool,
+}
+
+impl Member {
+    fn new(addr: SocketAddr, now_ms: u64) -> Self {
+        Self {
+            addr,
+            state: MemberState::Active,
+            recent_reports: VecDeque::new(),
+            measured: CollectedBenchStat::default(),
+            seq: SeqTracker::default(),
+            last_seen_ms: now_ms,
+            started: false,
+        }
+    }
+}
 
 /// A coordinator that collects reports from [`CoordinatedReporter`]s.
 ///
-/// # Note
-/// We assume that `CoordinatedReporter`s IDs are continous and start from 0.
+/// The reporters join the master either explicitly with [`CoordinatedReporter::register`],
+/// which lets the master assign or validate their ids,
+/// or implicitly with the id of their first report.
+/// An id can not be used by two running reporters at the same time:
+/// the reports of the second one are dropped.
+/// A reporter leaves once it sends its final report (see [`CoordinatedReporter::finish`]),
+/// or times out if the master receives nothing from it for a while (see [`CoordinatedReporterMaster::set_leave_timeout`]).
+/// Each membership change is logged.
 ///
+/// The master can also control the run (see [`CoordinatedReporterMaster::controlled_event_loop`]):
+/// it waits for the reporters to join, broadcasts START so that they begin at the same time,
+/// and broadcasts STOP once the run is over.
 pub struct CoordinatedReporterMaster {
-    num_reports: Vec<CollectedBenchStat>,
-    record_time: Vec<Instant>,
+    // the reporters that have joined, by their ids
+    members: BTreeMap<usize, Member>,
     master_socket: UdpSocket,
+    // how old a report can be to be aggregated, derived from the report interval if not set
+    staleness: Option<Duration>,
+    // how long a silent reporter is considered alive, derived from the report interval if not set
+    leave_timeout: Option<Duration>,
+    // the max spread of the throughputs of the reporters (and of their workers) that is not warned about
+    max_spread: Option<f64>,
+    // the aggregated throughputs of the measured window
+    interval_throughputs: Vec<f64>,
+    output: Option<StatFile>,
+    exporter: Option<PrometheusExporter>,
+    reassembler: Reassembler,
+    run_state: RunState,
 }
 
 impl CoordinatedReporterMaster {
     /// Create a new coordinated reporter master (async version)
-    pub async fn new(num_reporters: usize, sock: SocketAddr) -> std::io::Result<Self> {
+    pub async fn new(sock: SocketAddr) -> std::io::Result<Self> {
         let master_socket = UdpSocket::bind(sock).await?;
-        let mut num_reports = Vec::with_capacity(num_reporters);
-        let mut record_time = Vec::with_capacity(num_reporters);
-
-        let now = Instant::now();
-        for _ in 0..num_reporters {
-            num_reports.push(Default::default());
-            record_time.push(now);
-        }
-
         Ok(Self {
-            num_reports: num_reports,
-            record_time,
+            members: BTreeMap::new(),
             master_socket,
+            staleness: None,
+            leave_timeout: None,
+            max_spread: None,
+            interval_throughputs: Vec::new(),
+            output: None,
+            exporter: None,
+            reassembler: Reassembler::default(),
+            run_state: RunState::Running,
         })
     }
 
+    /// Set how old (by the end time of its interval) a report can be to be aggregated.
+    ///
+    /// By default, it is 1.5x of the report interval of the event loop.
+    /// The wall clock is divided into epochs of the report interval, i.e., `(k * interval, (k + 1) * interval]`,
+    /// and each tick aggregates, for each reporter, the latest report that ends in the latest epoch
+    /// ending no later than `now - staleness + report interval`,
+    /// so that the reports of the same epoch are aggregated, even if some of them arrive late.
+    /// The ticks fall in the middle of the epochs, so by default the reports have half an interval to arrive.
+    pub fn set_staleness(&mut self, staleness: Duration) {
+        self.staleness = Some(staleness);
+    }
+
+    /// The staleness window for a given report interval
+    fn staleness_of(&self, report_duration: Duration) -> Duration {
+        self.staleness.unwrap_or(report_duration * 3 / 2)
+    }
+
+    /// Set how long a reporter can be silent before it is considered gone.
+    ///
+    /// By default, it is 5x of the report interval of the event loop.
+    /// A timed out reporter rejoins once it sends a message again.
+    pub fn set_leave_timeout(&mut self, timeout: Duration) {
+        self.leave_timeout = Some(timeout);
+    }
+
+    /// The leave timeout for a given report interval
+    fn leave_timeout_of(&self, report_duration: Duration) -> Duration {
+        self.leave_timeout.unwrap_or(report_duration * 5)
+    }
+
+    /// Warn if the throughputs of the reporters of an epoch, or of the workers in a report,
+    /// spread more than `max_spread`, i.e., `(max - min) / mean` (e.g., 0.5).
+    ///
+    /// The workers are checked if the reporters break their reports down,
+    /// see [`super::SimpleBenchReporter::with_worker_breakdown`].
+    pub fn set_max_spread(&mut self, max_spread: f64) {
+        self.max_spread = Some(max_spread);
+    }
+
+    /// Write the received reports, and the aggregated report of each period, to a file
+    pub fn set_output(&mut self, output: StatFile) {
+        self.output = Some(output);
+    }
+
+    /// Export the received reports, and the aggregated report of each period, to a Prometheus endpoint
+    pub fn set_exporter(&mut self, exporter: PrometheusExporter) {
+        self.exporter = Some(exporter);
+    }
+
+    fn write_output(&mut self, stat: &CollectedBenchStat, aggregated: bool) {
+        if let Some(output) = self.output.as_mut() {
+            if let Err(e) = output.write(stat, aggregated) {
+                warn!("failed to write the report to the output file: {}", e);
+            }
+        }
+    }
+
+    /// The address the master listens on
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.master_socket.local_addr()
+    }
+
+    /// The number of reporters that are sending reports
+    pub fn num_active_reporters(&self) -> usize {
+        self.members
+            .values()
+            .filter(|m| m.state == MemberState::Active)
+            .count()
+    }
+
     /// Run an event loop to collect the reports from the reporters
     pub async fn report_event_loop(
         &mut self,
         duration: Duration,
         report_duration: Duration,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let start_time = Instant::now();
-        let mut tick_time = Instant::now();
-        let mut buf = [0u8; 1024];
+        self.report_event_loop_until(duration, report_duration, std::future::pending())
+            .await
+    }
+
+    /// Run an event loop to collect the reports from the reporters,
+    /// until `duration` passes or the `shutdown` future completes, e.g., on a Ctrl-C or a oneshot channel.
+    ///
+    /// The loop sleeps until a report arrives or a report interval passes, so it keeps no core busy.
+    pub async fn report_event_loop_until<F>(
+        &mut self,
+        duration: Duration,
+        report_duration: Duration,
+        shutdown: F,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: std::future::Future<Output = ()>,
+    {
+        self.event_loop(None, duration, report_duration, shutdown).await
+    }
+
+    /// Run a controlled run: wait for `num_reporters` reporters to join, broadcast START,
+    /// collect the reports for `duration`, then broadcast STOP and wait for the final reports of the reporters.
+    ///
+    /// The reporters call [`CoordinatedReporter::wait_start`] before running,
+    /// and stop once [`CoordinatedReporter::stop_requested`] returns true.
+    /// START and STOP are re-broadcast at each report interval to the reporters that miss them,
+    /// and the reporters joining late are started at once.
+    pub async fn controlled_event_loop(
+        &mut self,
+        num_reporters: usize,
+        duration: Duration,
+        report_duration: Duration,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.controlled_event_loop_until(num_reporters, duration, report_duration, std::future::pending())
+            .await
+    }
+
+    /// Run a controlled run (see [`CoordinatedReporterMaster::controlled_event_loop`]) until the `shutdown` future completes,
+    /// in which case STOP is broadcast at once.
+    pub async fn controlled_event_loop_until<F>(
+        &mut self,
+        num_reporters: usize,
+        duration: Duration,
+        report_duration: Duration,
+        shutdown: F,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: std::future::Future<Output = ()>,
+    {
+        self.event_loop(Some(num_reporters), duration, report_duration, shutdown)
+            .await
+    }
 
-        let mut cur_time = Instant::now();
+    async fn event_loop<F>(
+        &mut self,
+        num_reporters: Option<usize>,
+        duration: Duration,
+        report_duration: Duration,
+        shutdown: F,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: std::future::Future<Output = ()>,
+    {
+        let mut buf = vec![0u8; MAX_DATAGRAM_SZ];
 
-        while cur_time.duration_since(start_time) <= duration {
-            // recv message
-            match self.master_socket.try_recv_from(&mut buf) {
-                Ok((n, _addr)) => {
-                    let stat: CollectedBenchStat = serde_json::from_slice(&buf[..n])?;
-                    self.num_reports[stat.id] = stat;
-                    self.record_time[stat.id] = cur_time;
+        self.run_state = match num_reporters {
+            Some(n) => {
+                info!("waiting for {} reporters to join", n);
+                RunState::Waiting
+            }
+            None => RunState::Running,
+        };
+        let deadline = tokio::time::sleep(duration);
+        let mut ticker = tokio::time::interval_at(next_aligned_tick(report_duration), report_duration);
+        // a slow tick should not be followed by a burst of ticks
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        tokio::pin!(deadline);
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                res = self.master_socket.recv_from(&mut buf) => {
+                    let (n, addr) = res?;
+                    if let Some(msg) = self.reassembler.feed(addr, &buf[..n]) {
+                        match serde_json::from_slice::<ReportMessage>(&msg) {
+                            Ok(msg) => self.on_message(msg, addr, unix_time_ms()),
+                            Err(e) => warn!("malformed report from {}: {}", addr, e),
+                        }
+                    }
+                    if self.run_state == RunState::Waiting
+                        && self.num_active_reporters() >= num_reporters.unwrap_or(0)
+                    {
+                        info!("{} reporters joined, broadcast START", self.num_active_reporters());
+                        self.run_state = RunState::Running;
+                        self.broadcast(&MasterMessage::Start);
+                        // the run begins now
+                        deadline.as_mut().reset(tokio::time::Instant::now() + duration);
+                        ticker.reset_at(next_aligned_tick(report_duration));
+                    }
+                }
+                _ = ticker.tick(), if self.run_state != RunState::Waiting => {
+                    self.on_tick(unix_time_ms(), report_duration);
                 }
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    // continue;
+                _ = &mut deadline, if self.run_state != RunState::Waiting => {
+                    if num_reporters.is_none() || self.run_state == RunState::Stopping {
+                        break;
+                    }
+                    info!("the run is over, broadcast STOP");
+                    self.stop_run();
+                    // wait for the final reports
+                    deadline.as_mut().reset(tokio::time::Instant::now() + self.leave_timeout_of(report_duration));
                 }
-                Err(e) => {
-                    return Err(Box::new(e));
+                _ = &mut shutdown => {
+                    info!("report event loop is cancelled");
+                    if num_reporters.is_some() {
+                        self.stop_run();
+                    }
+                    break;
                 }
             }
-
-            if cur_time.duration_since(tick_time) >= report_duration {
-                let res = self.aggregrate_stats(&cur_time);
-                info!("reports: {}", res);
-                tick_time = Instant::now();
+            if self.run_state == RunState::Stopping
+                && self.members.values().all(|m| m.state != MemberState::Active)
+            {
+                info!("all the reporters have stopped");
+                break;
             }
-            cur_time = Instant::now();
         }
+        for (id, member) in self.members.iter() {
+            info!(
+                "reporter {} ({}): {} reports received, {} lost, {}",
+                id, member.addr, member.seq.received, member.seq.lost, member.state
+            );
+        }
+        info!("summary: {}", self.summary());
         Ok(())
     }
 
-    fn aggregrate_stats(&self, cur_time: &Instant) -> CollectedBenchStat {
-        let mut res = CollectedBenchStat::default();
-        for i in 0..self.num_reports.len() {
-            // we will filter out outdated reports
-            if cur_time.duration_since(self.record_time[i]) <= Duration::from_millis(1500) {
-                res = res + self.num_reports[i];
+    fn stop_run(&mut self) {
+        self.run_state = RunState::Stopping;
+        self.broadcast(&MasterMessage::Stop);
+    }
+
+    /// Send a control message to all the active reporters
+    fn broadcast(&self, msg: &MasterMessage) {
+        for member in self.members.values() {
+            if member.state == MemberState::Active {
+                self.reply(member.addr, msg);
+            }
+        }
+    }
+
+    /// The number of reports lost (so far) of all the reporters
+    pub fn num_lost_reports(&self) -> u64 {
+        self.members.values().map(|m| m.seq.lost).sum()
+    }
+
+    /// Add the reporter at `from` with the `requested` id (or the smallest unused id if it is None),
+    /// return its id and whether it is a new member.
+    ///
+    /// Fails if the id is used by another active reporter.
+    fn join(
+        &mut self,
+        requested: Option<usize>,
+        from: SocketAddr,
+        now_ms: u64,
+    ) -> Result<(usize, bool), String> {
+        let id = match requested {
+            Some(id) => id,
+            None => {
+                // a retried join request
+                let joined = self
+                    .members
+                    .iter()
+                    .find(|(_, m)| m.addr == from && m.state == MemberState::Active);
+                if let Some((id, _)) = joined {
+                    return Ok((*id, false));
+                }
+                (0..).find(|id| !self.members.contains_key(id)).unwrap()
+            }
+        };
+
+        match self.members.get_mut(&id) {
+            Some(member) if member.addr == from => {
+                if member.state == MemberState::TimedOut {
+                    member.state = MemberState::Active;
+                    info!("reporter {} ({}) is back", id, from);
+                }
+                return Ok((id, false));
+            }
+            Some(member) if member.state == MemberState::Active => {
+                return Err(format!("id {} is used by the reporter at {}", id, member.addr));
+            }
+            Some(member) => {
+                info!(
+                    "reporter {} rejoined from {}, the former reporter at {} is {}",
+                    id, from, member.addr, member.state
+                );
+                // keep the results of the former reporter
+                let measured = std::mem::take(&mut member.measured);
+                *member = Member::new(from, now_ms);
+                member.measured = measured;
+            }
+            None => {
+                self.members.insert(id, Member::new(from, now_ms));
+                info!(
+                    "reporter {} joined from {}, {} active reporters",
+                    id,
+                    from,
+                    self.num_active_reporters()
+                );
+            }
+        }
+        Ok((id, true))
+    }
+
+    /// Send a message to a reporter, errors are ignored since the message is resent if needed
+    fn reply(&self, to: SocketAddr, msg: &MasterMessage) {
+        let encoded = serde_json::to_vec(msg).expect("failed to encode the reply");
+        for datagram in encode_fragments(0, &encoded) {
+            if let Err(e) = self.master_socket.try_send_to(&datagram, to) {
+                warn!("failed to reply to the reporter at {}: {}", to, e);
+            }
+        }
+    }
+
+    /// Record a message from `from` received at `now_ms`
+    fn on_message(&mut self, msg: ReportMessage, from: SocketAddr, now_ms: u64) {
+        if let ReportBody::Ping(reporter_us) = msg.body {
+            let pong = MasterMessage::Pong {
+                reporter_us,
+                master_us: unix_time_us(),
+            };
+            self.reply(from, &pong);
+            return;
+        }
+        let id = match msg.body {
+            ReportBody::Join(requested) => {
+                let (reply, joined) = match self.join(requested, from, now_ms) {
+                    Ok((id, new)) => (MasterMessage::Welcome(id), new.then_some(id)),
+                    Err(reason) => {
+                        warn!("rejected the reporter at {}: {}", from, reason);
+                        (MasterMessage::Rejected(reason), None)
+                    }
+                };
+                self.reply(from, &reply);
+                match joined {
+                    Some(id) => id,
+                    // the retried requests are not counted
+                    None => return,
+                }
+            }
+            // reporters that do not register join with their first message
+            _ => match self.join(Some(msg.id), from, now_ms) {
+                Ok((id, _)) => id,
+                Err(reason) => {
+                    warn!("dropped a message from {}: {}", from, reason);
+                    return;
+                }
+            },
+        };
+
+        let member = self.members.get_mut(&id).unwrap();
+        if let ReportBody::Join(_) = msg.body {
+            // the messages before the join request, e.g., rejected ones, are not lost
+            member.seq = SeqTracker::starting_at(msg.seq);
+        }
+        if member.seq.record(msg.seq) == SeqStatus::Duplicate {
+            // a retried final report, whose ack is lost
+            if let ReportBody::Final = msg.body {
+                self.reply(from, &MasterMessage::Bye);
+            }
+            // merging a duplicated report again would inflate the results
+            return;
+        }
+        member.last_seen_ms = now_ms;
+        match msg.body {
+            ReportBody::Join(_) | ReportBody::Ping(_) => {}
+            ReportBody::Report(mut stat) => {
+                if self.run_state != RunState::Waiting {
+                    member.started = true;
+                }
+                stat.id = id;
+                self.on_report(*stat, now_ms);
+            }
+            ReportBody::Final => {
+                member.state = MemberState::Finished;
+                let lost = member.seq.lost;
+                info!(
+                    "reporter {} left, {} reports lost, {} active reporters",
+                    id,
+                    lost,
+                    self.num_active_reporters()
+                );
+                self.reply(from, &MasterMessage::Bye);
+            }
+        }
+    }
+
+    /// Record a report of a member received at `now_ms`
+    fn on_report(&mut self, mut stat: CollectedBenchStat, now_ms: u64) {
+        // reporters that do not timestamp their reports
+        if stat.end_time_ms == 0 {
+            stat.end_time_ms = now_ms;
+        }
+        let member = self.members.get_mut(&stat.id).unwrap();
+        if stat.phase == BenchPhase::Measure {
+            // the periods of the reports of one reporter are consecutive
+            let duration = member.measured.duration + stat.duration;
+            member.measured.merge(&stat);
+            member.measured.duration = duration;
+        }
+
+        let reports = &mut member.recent_reports;
+        // keep the reports ordered by their end time, since UDP may reorder them
+        let pos = reports
+            .iter()
+            .rposition(|r| r.end_time_ms <= stat.end_time_ms)
+            .map_or(0, |p| p + 1);
+        reports.insert(pos, stat.clone());
+
+        if let Some(max_spread) = self.max_spread {
+            check_workers(&stat, max_spread);
+        }
+        self.write_output(&stat, false);
+        if let Some(exporter) = self.exporter.as_ref() {
+            exporter.update_report(&stat);
+        }
+    }
+
+    /// Aggregate and report the reports of the window ending at `now_ms`
+    fn on_tick(&mut self, now_ms: u64, report_duration: Duration) {
+        // resend the control messages that may be missed by the reporters
+        for member in self.members.values().filter(|m| m.state == MemberState::Active) {
+            match self.run_state {
+                RunState::Running if !member.started => self.reply(member.addr, &MasterMessage::Start),
+                RunState::Stopping => self.reply(member.addr, &MasterMessage::Stop),
+                _ => {}
+            }
+        }
+
+        let mut res = self.aggregrate_stats(now_ms, report_duration);
+        let (epoch_start, epoch_end) = self.epoch_of(now_ms, report_duration);
+        res.start_time_ms = epoch_start;
+        res.end_time_ms = epoch_end;
+        info!("reports: {}", res);
+        self.write_output(&res, true);
+        if let Some(exporter) = self.exporter.as_ref() {
+            exporter.update_cluster(&res);
+        }
+        let fresh = self.fresh_reports(now_ms, report_duration);
+        // per-client breakdown, so that a slow client is visible
+        for stat in fresh.iter() {
+            info!("  - {}, lost reports: {}", stat, self.members[&stat.id].seq.lost);
+        }
+        if let Some(max_spread) = self.max_spread {
+            self.check_reporters(&fresh, max_spread);
+        }
+        if res.phase == BenchPhase::Measure && !fresh.is_empty() {
+            self.interval_throughputs.push(res.throughput);
+        }
+
+        // the reports older than the epoch will never be aggregated
+        let oldest = epoch_start;
+        let timeout = self.leave_timeout_of(report_duration);
+        for (id, member) in self.members.iter_mut() {
+            while member.recent_reports.front().is_some_and(|r| r.end_time_ms <= oldest) {
+                member.recent_reports.pop_front();
+            }
+            if member.state == MemberState::Active
+                && now_ms.saturating_sub(member.last_seen_ms) > timeout.as_millis() as u64
+            {
+                member.state = MemberState::TimedOut;
+                warn!(
+                    "reporter {} ({}) timed out, nothing received in {:?}",
+                    id, member.addr, timeout
+                );
             }
         }
+    }
+
+    /// Warn if the reports of an epoch are imbalanced, e.g., a client is starved by the server threads
+    fn check_reporters(&self, reports: &[&CollectedBenchStat], max_spread: f64) {
+        if let Some(s) = spread(reports.iter().map(|r| r.throughput)) {
+            if s > max_spread {
+                let slowest = reports
+                    .iter()
+                    .min_by(|a, b| a.throughput.total_cmp(&b.throughput))
+                    .unwrap();
+                warn!(
+                    "the reporters are imbalanced, spread: {:.2}, slowest reporter {}: {:.4} Mops/s",
+                    s, slowest.id, slowest.throughput
+                );
+            }
+        }
+    }
+
+    /// The summary of the measured window of all the reporters
+    pub fn summary(&self) -> BenchSummary {
+        let mut total = CollectedBenchStat::default();
+        for member in self.members.values() {
+            total.merge(&member.measured);
+        }
+        BenchSummary::new(&self.interval_throughputs, &total)
+    }
+
+    /// The epoch `(start, end]` aggregated by the tick at `now_ms`, see [`CoordinatedReporterMaster::set_staleness`]
+    fn epoch_of(&self, now_ms: u64, report_duration: Duration) -> (u64, u64) {
+        let staleness = self.staleness_of(report_duration).as_millis() as u64;
+        let interval = std::cmp::max(report_duration.as_millis() as u64, 1);
+        let end = (now_ms + interval).saturating_sub(staleness) / interval * interval;
+        (end.saturating_sub(interval), end)
+    }
+
+    /// The latest report of each reporter that ends in the epoch of the tick at `now_ms`
+    fn fresh_reports(&self, now_ms: u64, report_duration: Duration) -> Vec<&CollectedBenchStat> {
+        let (window_start, window_end) = self.epoch_of(now_ms, report_duration);
+        self.members
+            .values()
+            .filter_map(|member| {
+                member
+                    .recent_reports
+                    .iter()
+                    .rev()
+                    .find(|r| r.end_time_ms > window_start && r.end_time_ms <= window_end)
+            })
+            .collect()
+    }
+
+    /// Aggregate the reports: the average latency is weighted by the ops of each reporter,
+    /// and the percentiles are computed from the merged latency samples of all the reporters.
+    fn aggregrate_stats(&self, now_ms: u64, report_duration: Duration) -> CollectedBenchStat {
+        let mut res = CollectedBenchStat::default();
+        for stat in self.fresh_reports(now_ms, report_duration) {
+            res.merge(stat);
+        }
         res
     }
 }
 
+/// The instant of the next tick of the master, which falls in the middle of an epoch of the wall clock
+fn next_aligned_tick(report_duration: Duration) -> tokio::time::Instant {
+    let interval = std::cmp::max(report_duration.as_micros() as u64, 1);
+    let delay = (interval / 2 + interval - unix_time_us() % interval) % interval;
+    tokio::time::Instant::now() + Duration::from_micros(delay)
+}
+
 /// A reporter that reports the throughput and latency of workers from this machine.
 pub struct CoordinatedReporter<R: BenchReporter> {
     // We leverage the inner reporter to collect stats
     inner: R,
     master_addr: SocketAddr,
     master_socket: UdpSocket,
+    // the sequence number of the next message
+    seq: u64,
+    // the id assigned by the master, or the id of the last report if not registered
+    id: usize,
+    registered: bool,
+    // the messages from the master, and the buffer to receive them
+    reassembler: Reassembler,
+    recv_buf: Vec<u8>,
+    // whether the master has broadcast START or STOP
+    started: bool,
+    stopped: bool,
+    // the clock of the master minus the local clock, in microseconds
+    clock_offset_us: i64,
 }
 
 impl<R> CoordinatedReporter<R>
@@ -150,17 +742,235 @@ where
             inner: reporter,
             master_addr,
             master_socket,
+            seq: 0,
+            id: 0,
+            registered: false,
+            reassembler: Reassembler::default(),
+            recv_buf: vec![0u8; MAX_DATAGRAM_SZ],
+            started: false,
+            stopped: false,
+            clock_offset_us: 0,
         })
     }
+
+    /// Register at the master, and return the id of this reporter.
+    ///
+    /// If `id` is None, the master assigns the smallest unused id,
+    /// otherwise it checks that `id` is not used by another running reporter.
+    /// Once registered, the reports sent to the master carry the assigned id instead of the one of the inner reporter,
+    /// and their timestamps are in the clock of the master (see [`CoordinatedReporter::sync_clock`]).
+    /// Fails if the master rejects the reporter, or does not respond.
+    pub async fn register(&mut self, id: Option<usize>) -> std::io::Result<usize> {
+        let msg = self.message(ReportBody::Join(id));
+        for _ in 0..REGISTER_RETRIES {
+            // the retries share the seq, so the master counts them once
+            self.send_message(&msg).await?;
+            let deadline = tokio::time::Instant::now() + REGISTER_TIMEOUT;
+            while let Ok(res) =
+                tokio::time::timeout_at(deadline, self.master_socket.recv_from(&mut self.recv_buf)).await
+            {
+                let (n, from) = res?;
+                match self.decode(from, n) {
+                    Some(MasterMessage::Welcome(id)) => {
+                        self.id = id;
+                        self.registered = true;
+                        if let Err(e) = self.sync_clock().await {
+                            warn!("failed to sync the clock with the master: {}", e);
+                        }
+                        return Ok(id);
+                    }
+                    Some(MasterMessage::Rejected(reason)) => {
+                        return Err(std::io::Error::new(std::io::ErrorKind::AddrInUse, reason));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!("the master at {} does not respond", self.master_addr),
+        ))
+    }
+
+    /// Estimate the offset of the clock of the master to the local clock, NTP-style, and return it in microseconds.
+    ///
+    /// The reporter pings the master several times, and takes the round trip with the least delay,
+    /// assuming that the master receives the ping in the middle of it.
+    /// The offset is applied to the timestamps of the following reports sent to the master,
+    /// so that the master can tell which epoch each report belongs to, even if the hosts' clocks differ.
+    pub async fn sync_clock(&mut self) -> std::io::Result<i64> {
+        // (round trip, offset) of the best sample
+        let mut best: Option<(u64, i64)> = None;
+        for _ in 0..CLOCK_SYNC_PINGS {
+            let sent_us = unix_time_us();
+            let ping = ReportMessage {
+                seq: self.seq,
+                id: self.id,
+                body: ReportBody::Ping(sent_us),
+            };
+            self.send_message(&ping).await?;
+            let deadline = tokio::time::Instant::now() + REGISTER_TIMEOUT;
+            while let Ok(res) =
+                tokio::time::timeout_at(deadline, self.master_socket.recv_from(&mut self.recv_buf)).await
+            {
+                let (n, from) = res?;
+                let (reporter_us, master_us) = match self.decode(from, n) {
+                    Some(MasterMessage::Pong { reporter_us, master_us }) => (reporter_us, master_us),
+                    _ => continue,
+                };
+                // the pong of an earlier ping that timed out
+                if reporter_us != sent_us {
+                    continue;
+                }
+                let rtt = unix_time_us().saturating_sub(sent_us);
+                let offset = master_us as i64 - (sent_us + rtt / 2) as i64;
+                if best.is_none_or(|(best_rtt, _)| rtt < best_rtt) {
+                    best = Some((rtt, offset));
+                }
+                break;
+            }
+        }
+
+        let (rtt, offset) = best.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("the master at {} does not respond", self.master_addr),
+            )
+        })?;
+        info!("clock offset to the master: {} µs, round trip: {} µs", offset, rtt);
+        self.clock_offset_us = offset;
+        Ok(offset)
+    }
+
+    /// The offset of the clock of the master to the local clock (in microseconds), 0 if not synced
+    pub fn clock_offset_us(&self) -> i64 {
+        self.clock_offset_us
+    }
+
+    /// Convert a local timestamp (in milliseconds) to the clock of the master
+    fn to_master_clock(&self, time_ms: u64) -> u64 {
+        if time_ms == 0 {
+            return 0;
+        }
+        ((time_ms as i64 * 1000 + self.clock_offset_us) / 1000).max(0) as u64
+    }
+
+    /// Wait for the master to START a controlled run, see [`CoordinatedReporterMaster::controlled_event_loop`].
+    ///
+    /// The reporter should be registered, so that the master knows whom to start.
+    pub async fn wait_start(&mut self) -> std::io::Result<()> {
+        self.wait_start_until(std::future::pending()).await?;
+        Ok(())
+    }
+
+    /// Wait for the master to START a controlled run (see [`CoordinatedReporter::wait_start`])
+    /// until the `shutdown` future completes, e.g., on Ctrl-C.
+    /// Return whether the run has started.
+    pub async fn wait_start_until<F>(&mut self, shutdown: F) -> std::io::Result<bool>
+    where
+        F: std::future::Future<Output = ()>,
+    {
+        tokio::pin!(shutdown);
+        while !self.started {
+            tokio::select! {
+                res = self.master_socket.recv_from(&mut self.recv_buf) => {
+                    let (n, from) = res?;
+                    self.decode(from, n);
+                }
+                _ = &mut shutdown => {
+                    info!("stopped waiting for the START of the master");
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Whether the master has broadcast STOP, without blocking.
+    /// The caller checks it periodically, e.g., after each report.
+    pub fn stop_requested(&mut self) -> bool {
+        while let Ok((n, from)) = self.master_socket.try_recv_from(&mut self.recv_buf) {
+            self.decode(from, n);
+        }
+        self.stopped
+    }
+
+    /// Decode a datagram of `n` bytes from the master in the receive buffer, and record the control messages
+    fn decode(&mut self, from: SocketAddr, n: usize) -> Option<MasterMessage> {
+        let msg = self.reassembler.feed(from, &self.recv_buf[..n])?;
+        let msg = match serde_json::from_slice::<MasterMessage>(&msg) {
+            Ok(msg) => msg,
+            Err(e) => {
+                warn!("malformed message from {}: {}", from, e);
+                return None;
+            }
+        };
+        match msg {
+            MasterMessage::Start => self.started = true,
+            // a reporter that misses START still stops
+            MasterMessage::Stop => {
+                self.started = true;
+                self.stopped = true;
+            }
+            _ => {}
+        }
+        Some(msg)
+    }
+
+    /// Tell the master that this reporter has finished cleanly, and wait for its ack.
+    /// The reporter id is the one of the last report.
+    /// Fails if the master does not respond, e.g., it has exited.
+    pub async fn finish(&mut self) -> std::io::Result<()> {
+        let msg = self.message(ReportBody::Final);
+        for _ in 0..REGISTER_RETRIES {
+            // the retries share the seq, so the master records them once
+            self.send_message(&msg).await?;
+            let deadline = tokio::time::Instant::now() + REGISTER_TIMEOUT;
+            while let Ok(res) =
+                tokio::time::timeout_at(deadline, self.master_socket.recv_from(&mut self.recv_buf)).await
+            {
+                let (n, from) = res?;
+                if let Some(MasterMessage::Bye) = self.decode(from, n) {
+                    return Ok(());
+                }
+            }
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!("the master at {} does not acknowledge the final report", self.master_addr),
+        ))
+    }
+
+    fn message(&mut self, body: ReportBody) -> ReportMessage {
+        let msg = ReportMessage {
+            seq: self.seq,
+            id: self.id,
+            body,
+        };
+        self.seq += 1;
+        msg
+    }
+
+    async fn send_message(&self, msg: &ReportMessage) -> std::io::Result<()> {
+        let encoded = serde_json::to_vec(msg).map_err(std::io::Error::other)?;
+        for datagram in encode_fragments(msg.seq, &encoded) {
+            self.master_socket.send_to(&datagram, self.master_addr).await?;
+        }
+        Ok(())
+    }
 }
 
 impl<R> BenchReporter for CoordinatedReporter<R>
 where
     R: BenchReporter,
 {
+    fn set_phase(&mut self, phase: BenchPhase) {
+        self.inner.set_phase(phase);
+    }
+
     // 1. collect the stats
     // 2. send the stats to the master
-    fn report_collected_stat(&mut self, stats: &Vec<Arc<BenchStat>>) -> CollectedBenchStat {
+    fn report_collected_stat(&mut self, stats: &[BenchStat]) -> CollectedBenchStat {
         self.inner.report_collected_stat(stats)
     }
 }
@@ -169,19 +979,23 @@ impl<R> AsyncBenchReporter for CoordinatedReporter<R>
 where
     R: BenchReporter,
 {
-    async fn async_report_collect_stat(
-        &mut self,
-        stats: &Vec<Arc<BenchStat>>,
-    ) -> CollectedBenchStat {
-        let res = self.inner.report_collected_stat(stats);
-        self.master_socket
-            .send_to(
-                serde_json::to_vec(&res).unwrap().as_slice(),
-                self.master_addr,
-            )
-            .await
-            .expect("send UDP message to master failed");
-        res
+    fn async_report_collect_stat<'a>(&'a mut self, stats: &'a [BenchStat]) -> ReportFuture<'a> {
+        Box::pin(async move {
+            let mut res = self.inner.report_collected_stat(stats);
+            if self.registered {
+                res.id = self.id;
+            } else {
+                self.id = res.id;
+            }
+            res.start_time_ms = self.to_master_clock(res.start_time_ms);
+            res.end_time_ms = self.to_master_clock(res.end_time_ms);
+            let msg = self.message(ReportBody::Report(Box::new(res.clone())));
+            // a transient error (e.g., ENOBUFS) loses the report, which the master counts by its seq
+            if let Err(e) = self.send_message(&msg).await {
+                warn!("failed to send report {} to the master: {}", msg.seq, e);
+            }
+            res
+        })
     }
 }
 
@@ -206,7 +1020,504 @@ mod tests {
         rt.block_on(async {
             let r = SimpleBenchReporter::new_with_id(0);
             // the addr is not important here, can be any addr
-            let _ = CoordinatedReporter::new("127.0.0.1:8080".parse().unwrap(), r);
+            let _ = CoordinatedReporter::new("127.0.0.1:8080".parse().unwrap(), r).await;
+        });
+    }
+
+    #[test]
+    fn test_master_aggregates_matching_windows() {
+        use super::*;
+        use tokio::runtime::Runtime;
+
+        let report = |id: usize, throughput: f64, end_time_ms: u64| CollectedBenchStat {
+            id,
+            throughput,
+            end_time_ms,
+            ..Default::default()
+        };
+        let interval = Duration::from_millis(100);
+
+        let rt = Runtime::new().unwrap();
+        let mut master = rt
+            .block_on(CoordinatedReporterMaster::new("127.0.0.1:0".parse().unwrap()))
+            .unwrap();
+        // the staleness is derived from the interval by default
+        assert_eq!(master.staleness_of(interval), Duration::from_millis(150));
+
+        // a tick in the middle of an epoch
+        let now = 10_050;
+        for id in 0..2 {
+            let addr = SocketAddr::from(([127, 0, 0, 1], 1000 + id as u16));
+            assert_eq!(master.join(Some(id), addr, now - 200), Ok((id, true)));
+        }
+        // reporter 0: a late report of the epoch, and a newer one of the next epoch
+        master.on_report(report(0, 1.0, 9_960), now - 10);
+        master.on_report(report(0, 5.0, 10_040), now);
+        // reporter 1 arrives out of order
+        master.on_report(report(1, 2.0, 9_990), now);
+        master.on_report(report(1, 9.0, 9_890), now);
+        // the epoch is (9_900, 10_000]
+        assert_eq!(master.epoch_of(now, interval), (9_900, 10_000));
+        let res = master.aggregrate_stats(now, interval);
+        assert_eq!(res.throughput, 3.0);
+
+        // a stale reporter is excluded
+        master.set_staleness(Duration::from_millis(50));
+        let res = master.aggregrate_stats(now, interval);
+        assert_eq!(res.throughput, 5.0);
+
+        master.on_tick(now, interval);
+        assert!(master.members.values().all(|m| m.recent_reports.len() <= 1));
+    }
+
+    #[test]
+    fn test_master_worker_breakdown() {
+        use super::*;
+        use crate::reporter::breakdown_of;
+        use tokio::runtime::Runtime;
+
+        let interval = Duration::from_millis(100);
+        let rt = Runtime::new().unwrap();
+        let mut master = rt
+            .block_on(CoordinatedReporterMaster::new("127.0.0.1:0".parse().unwrap()))
+            .unwrap();
+        master.set_max_spread(0.5);
+
+        let now = 10_050;
+        for id in 0..2 {
+            let addr = SocketAddr::from(([127, 0, 0, 1], 1000 + id as u16));
+            assert_eq!(master.join(Some(id), addr, now - 200), Ok((id, true)));
+            // the second worker of reporter 1 is starved
+            let mut stat = CollectedBenchStat {
+                id,
+                throughput: 2.0,
+                end_time_ms: 9_990,
+                workers: breakdown_of(&[(100_000, None), (100_000 / (1 + 9 * id as u64), None)], 1e5),
+                ..Default::default()
+            };
+            // the breakdown goes through the transport
+            let msg = serde_json::to_vec(&stat).unwrap();
+            stat = serde_json::from_slice(&msg).unwrap();
+            master.on_report(stat, now);
+        }
+
+        let reports = master.fresh_reports(now, interval);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].worker_spread(), Some(0.0));
+        assert!(reports[1].worker_spread().unwrap() > 0.5);
+        assert!(reports[1].to_string().contains("workers: [#0: 1.0000 Mops/s (90.9%), #1: 0.1000 Mops/s (9.1%)]"));
+
+        // the workers of different reporters are not mixed up
+        let res = master.aggregrate_stats(now, interval);
+        assert!(res.workers.is_empty());
+        master.on_tick(now, interval);
+    }
+
+    #[test]
+    fn test_master_event_loop_cancel() {
+        use super::*;
+        use crate::SimpleBenchReporter;
+        use tokio::runtime::Runtime;
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut master = CoordinatedReporterMaster::new("127.0.0.1:0".parse().unwrap())
+                .await
+                .unwrap();
+            let mut reporter = CoordinatedReporter::new(
+                master.local_addr().unwrap(),
+                SimpleBenchReporter::new_with_id(0),
+            )
+            .await
+            .unwrap();
+
+            let (cancel, cancelled) = tokio::sync::oneshot::channel::<()>();
+            let begin = std::time::Instant::now();
+            let (res, _) = tokio::join!(
+                master.report_event_loop_until(
+                    Duration::from_secs(60),
+                    Duration::from_millis(10),
+                    async {
+                        let _ = cancelled.await;
+                    },
+                ),
+                async {
+                    let mut stat = BenchStat::default();
+                    stat.finished_batch_ops(100);
+                    // a report with a large histogram spans several datagrams
+                    for i in 0..100 {
+                        stat.record_latency_ns(1 << (i % 40));
+                        stat.record_latency_ns(1000 + i * 1000);
+                    }
+                    reporter.async_report_collect_stat(&[stat]).await;
+                    reporter.finish().await.unwrap();
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    cancel.send(()).unwrap();
+                }
+            );
+            res.unwrap();
+            assert!(begin.elapsed() < Duration::from_secs(10));
+            let member = &master.members[&0];
+            assert_eq!(member.measured.num_ops, 100);
+            assert_eq!(member.measured.latency.count(), 200);
+            assert_eq!(member.state, MemberState::Finished);
+            assert_eq!(master.num_lost_reports(), 0);
+        });
+    }
+
+    #[test]
+    fn test_master_counts_lost_reports() {
+        use super::*;
+        use tokio::runtime::Runtime;
+
+        let rt = Runtime::new().unwrap();
+        let mut master = rt
+            .block_on(CoordinatedReporterMaster::new("127.0.0.1:0".parse().unwrap()))
+            .unwrap();
+        let from = "127.0.0.1:1000".parse().unwrap();
+        let msg = |seq: u64| ReportMessage {
+            seq,
+            id: 0,
+            body: ReportBody::Report(Box::default()),
+        };
+        master.on_message(msg(0), from, 1);
+        master.on_message(msg(3), from, 2);
+        assert_eq!(master.num_lost_reports(), 2);
+        master.on_message(
+            ReportMessage {
+                seq: 4,
+                id: 0,
+                body: ReportBody::Final,
+            },
+            from,
+            3,
+        );
+        assert_eq!(master.members[&0].state, MemberState::Finished);
+        assert_eq!(master.members[&0].seq.received, 3);
+    }
+
+    #[test]
+    fn test_master_drops_duplicated_reports() {
+        use super::*;
+        use tokio::runtime::Runtime;
+
+        let rt = Runtime::new().unwrap();
+        let mut master = rt
+            .block_on(CoordinatedReporterMaster::new("127.0.0.1:0".parse().unwrap()))
+            .unwrap();
+        let from = "127.0.0.1:1000".parse().unwrap();
+        let msg = |seq: u64| ReportMessage {
+            seq,
+            id: 0,
+            body: ReportBody::Report(Box::new(CollectedBenchStat {
+                num_ops: 10,
+                num_bytes: 640,
+                duration: Duration::from_secs(1),
+                ..Default::default()
+            })),
+        };
+        master.on_message(msg(0), from, 1);
+        master.on_message(msg(2), from, 2);
+        let summary = master.summary();
+        assert_eq!(summary.num_ops, 20);
+
+        // a duplicated datagram is neither merged again nor makes up for the lost report
+        master.on_message(msg(2), from, 3);
+        master.on_message(msg(0), from, 3);
+        assert_eq!(master.summary(), summary);
+        assert_eq!(master.num_lost_reports(), 1);
+        assert_eq!(master.members[&0].seq.received, 2);
+
+        // but the late one is
+        master.on_message(msg(1), from, 4);
+        assert_eq!(master.summary().num_ops, 30);
+        assert_eq!(master.num_lost_reports(), 0);
+    }
+
+    #[test]
+    fn test_master_acks_final_reports() {
+        use super::*;
+        use tokio::runtime::Runtime;
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut master = CoordinatedReporterMaster::new("127.0.0.1:0".parse().unwrap())
+                .await
+                .unwrap();
+            // the replies are sent without blocking, as in the event loop
+            master.master_socket.writable().await.unwrap();
+            let reporter = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let from = reporter.local_addr().unwrap();
+            let last = ReportMessage {
+                seq: 0,
+                id: 0,
+                body: ReportBody::Final,
+            };
+            let recv_reply = || async {
+                let mut buf = vec![0u8; MAX_DATAGRAM_SZ];
+                let n = tokio::time::timeout(Duration::from_secs(10), reporter.recv(&mut buf))
+                    .await
+                    .expect("the master does not reply")
+                    .unwrap();
+                let mut reassembler = Reassembler::default();
+                let msg = reassembler.feed(from, &buf[..n]).unwrap();
+                serde_json::from_slice::<MasterMessage>(&msg).unwrap()
+            };
+
+            master.on_message(last.clone(), from, 1);
+            assert_eq!(recv_reply().await, MasterMessage::Bye);
+            // a retried final report is acked again, but recorded once
+            master.on_message(last, from, 2);
+            assert_eq!(recv_reply().await, MasterMessage::Bye);
+            assert_eq!(master.members[&0].state, MemberState::Finished);
+            assert_eq!(master.members[&0].seq.received, 1);
+        });
+    }
+
+    #[test]
+    fn test_master_membership() {
+        use super::*;
+        use tokio::runtime::Runtime;
+
+        let rt = Runtime::new().unwrap();
+        let mut master = rt
+            .block_on(CoordinatedReporterMaster::new("127.0.0.1:0".parse().unwrap()))
+            .unwrap();
+        let a = "127.0.0.1:1000".parse().unwrap();
+        let b = "127.0.0.1:1001".parse().unwrap();
+        let c = "127.0.0.1:1002".parse().unwrap();
+
+        // ids are assigned, or validated, beyond any fixed number of clients
+        assert_eq!(master.join(Some(100), a, 0), Ok((100, true)));
+        assert_eq!(master.join(None, b, 0), Ok((0, true)));
+        // a retried join request
+        assert_eq!(master.join(None, b, 0), Ok((0, false)));
+        assert!(master.join(Some(100), c, 0).is_err());
+        assert_eq!(master.num_active_reporters(), 2);
+
+        // the reports with a used id are dropped
+        let report = ReportMessage {
+            seq: 0,
+            id: 100,
+            body: ReportBody::Report(Box::new(CollectedBenchStat {
+                num_ops: 10,
+                ..Default::default()
+            })),
+        };
+        master.on_message(report.clone(), c, 1);
+        assert_eq!(master.members[&100].seq.received, 0);
+        master.on_message(report, a, 1);
+        assert_eq!(master.members[&100].measured.num_ops, 10);
+
+        // silent reporters time out, and come back once they report again
+        master.on_tick(2_000, Duration::from_millis(100));
+        assert_eq!(master.num_active_reporters(), 0);
+        assert_eq!(master.join(Some(100), a, 2_000), Ok((100, false)));
+        assert_eq!(master.num_active_reporters(), 1);
+
+        // the id of a finished reporter can be taken over, keeping its results
+        master.on_message(
+            ReportMessage {
+                seq: 1,
+                id: 100,
+                body: ReportBody::Final,
+            },
+            a,
+            2_001,
+        );
+        assert_eq!(master.join(Some(100), c, 2_002), Ok((100, true)));
+        assert_eq!(master.members[&100].addr, c);
+        assert_eq!(master.summary().num_ops, 10);
+    }
+
+    #[test]
+    fn test_reporter_register() {
+        use super::*;
+        use crate::SimpleBenchReporter;
+        use tokio::runtime::Runtime;
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut master = CoordinatedReporterMaster::new("127.0.0.1:0".parse().unwrap())
+                .await
+                .unwrap();
+            let addr = master.local_addr().unwrap();
+            let mut first = CoordinatedReporter::new(addr, SimpleBenchReporter::new_with_id(7))
+                .await
+                .unwrap();
+            let mut second = CoordinatedReporter::new(addr, SimpleBenchReporter::new_with_id(7))
+                .await
+                .unwrap();
+
+            let (cancel, cancelled) = tokio::sync::oneshot::channel::<()>();
+            let (res, _) = tokio::join!(
+                master.report_event_loop_until(
+                    Duration::from_secs(60),
+                    Duration::from_millis(10),
+                    async {
+                        let _ = cancelled.await;
+                    },
+                ),
+                async {
+                    assert_eq!(first.register(Some(7)).await.unwrap(), 7);
+                    let err = second.register(Some(7)).await.unwrap_err();
+                    assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+                    assert_eq!(second.register(None).await.unwrap(), 0);
+
+                    // the reports carry the assigned id
+                    let res = second.async_report_collect_stat(&[BenchStat::default()]).await;
+                    assert_eq!(res.id, 0);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    cancel.send(()).unwrap();
+                }
+            );
+            res.unwrap();
+            assert_eq!(master.members[&0].seq.received, 2);
+            assert_eq!(master.members[&0].seq.lost, 0);
+        });
+    }
+
+    #[test]
+    fn test_controlled_run() {
+        use super::*;
+        use crate::SimpleBenchReporter;
+        use tokio::runtime::Runtime;
+
+        async fn run_reporter(master_addr: SocketAddr) -> usize {
+            let mut reporter = CoordinatedReporter::new(master_addr, SimpleBenchReporter::new())
+                .await
+                .unwrap();
+            reporter.register(None).await.unwrap();
+            reporter.wait_start().await.unwrap();
+            let mut num_reports = 0;
+            while !reporter.stop_requested() {
+                let mut stat = BenchStat::default();
+                stat.finished_batch_ops(10);
+                reporter.async_report_collect_stat(&[stat]).await;
+                num_reports += 1;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            reporter.finish().await.unwrap();
+            num_reports
+        }
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut master = CoordinatedReporterMaster::new("127.0.0.1:0".parse().unwrap())
+                .await
+                .unwrap();
+            let addr = master.local_addr().unwrap();
+            let begin = std::time::Instant::now();
+            let (res, first, second) = tokio::join!(
+                master.controlled_event_loop(2, Duration::from_millis(200), Duration::from_millis(20)),
+                run_reporter(addr),
+                async {
+                    // the run starts once the second reporter joins
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    run_reporter(addr).await
+                },
+            );
+            res.unwrap();
+            // the master returns once all the reporters have stopped, long before the leave timeout
+            assert!(begin.elapsed() < Duration::from_secs(10));
+            assert!(first > 0 && second > 0);
+            assert!(first.abs_diff(second) <= 5);
+            assert_eq!(master.members.len(), 2);
+            assert!(master.members.values().all(|m| m.state == MemberState::Finished));
+        });
+    }
+
+    #[test]
+    fn test_reporter_survives_send_errors() {
+        use super::*;
+        use crate::SimpleBenchReporter;
+        use tokio::runtime::Runtime;
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            // sending to port 0 fails
+            let mut reporter =
+                CoordinatedReporter::new("127.0.0.1:0".parse().unwrap(), SimpleBenchReporter::new())
+                    .await
+                    .unwrap();
+            let last = reporter.message(ReportBody::Final);
+            assert!(reporter.send_message(&last).await.is_err());
+            reporter.async_report_collect_stat(&[BenchStat::default()]).await;
+            reporter.async_report_collect_stat(&[BenchStat::default()]).await;
+            // the lost reports still take their seqs
+            assert_eq!(reporter.seq, 3);
+        });
+    }
+
+    #[test]
+    fn test_reporter_wait_start_until() {
+        use super::*;
+        use crate::SimpleBenchReporter;
+        use tokio::runtime::Runtime;
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            // a master that never starts the run
+            let master = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let mut reporter =
+                CoordinatedReporter::new(master.local_addr().unwrap(), SimpleBenchReporter::new())
+                    .await
+                    .unwrap();
+            let started = tokio::time::timeout(
+                Duration::from_secs(10),
+                reporter.wait_start_until(tokio::time::sleep(Duration::from_millis(20))),
+            )
+            .await
+            .expect("the shutdown is missed");
+            assert!(!started.unwrap());
+            assert!(!reporter.stop_requested());
+        });
+    }
+
+    #[test]
+    fn test_reporter_clock_sync() {
+        use super::*;
+        use crate::SimpleBenchReporter;
+        use tokio::runtime::Runtime;
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut master = CoordinatedReporterMaster::new("127.0.0.1:0".parse().unwrap())
+                .await
+                .unwrap();
+            let mut reporter =
+                CoordinatedReporter::new(master.local_addr().unwrap(), SimpleBenchReporter::new())
+                    .await
+                    .unwrap();
+
+            let (cancel, cancelled) = tokio::sync::oneshot::channel::<()>();
+            let (res, _) = tokio::join!(
+                master.report_event_loop_until(
+                    Duration::from_secs(60),
+                    Duration::from_millis(10),
+                    async {
+                        let _ = cancelled.await;
+                    },
+                ),
+                async {
+                    reporter.register(None).await.unwrap();
+                    // the same clock
+                    assert!(reporter.clock_offset_us().abs() < 100_000);
+
+                    // a reporter whose clock is 10 seconds behind the master
+                    reporter.clock_offset_us = 10_000_000;
+                    let res = reporter.async_report_collect_stat(&[BenchStat::default()]).await;
+                    let now = unix_time_ms();
+                    assert!(res.end_time_ms >= now + 9_000 && res.end_time_ms <= now + 11_000);
+                    assert!(res.start_time_ms <= res.end_time_ms);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    cancel.send(()).unwrap();
+                }
+            );
+            res.unwrap();
+            // the pings are not counted as reports
+            assert_eq!(master.members[&0].seq.received, 2);
+            assert_eq!(master.members[&0].seq.lost, 0);
         });
     }
 }
@@ -0,0 +1,264 @@
+//! Write the reports to a file, so that the results can be processed without scraping the logs.
+//!
+//! Each report is written as one timestamped record, either as a JSON line or as a CSV row (see [`OutputFormat`]).
+//! Besides the fields of [`CollectedBenchStat`], each record carries:
+//! - `timestamp_ms`: the wall-clock time (milliseconds since the UNIX epoch) when the record is written;
+//! - `run_id`: the id of the run, shared by all the records of a file;
+//! - `aggregated`: whether the record is the aggregation of all the reporters (written by the master).
+//!
+//! Example usage:
+//!
+//! ```no_run
+//! use netbencher_core::{BenchRunner, FileReporter, SimpleBenchReporter};
+//!
+//! let runner = BenchRunner::new(1);
+//! runner.run(|_, runner, mut stat, _| {
+//!     while runner.running() {
+//!         stat.finished_one_op();
+//!     }
+//! }, ());
+//!
+//! let mut reporter = FileReporter::new("results.jsonl", SimpleBenchReporter::new())
+//!     .expect("failed to create the output file");
+//! for _ in 0..10 {
+//!     std::thread::sleep(std::time::Duration::from_secs(1));
+//!     runner.report(&mut reporter);
+//! }
+//! runner.stop().unwrap();
+//! ```
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+
+use log::warn;
+use serde_derive::Serialize;
+
+use super::{unix_time_ms, BenchReporter, BenchStat, CollectedBenchStat};
+use crate::BenchPhase;
+
+/// The format of the records in the output file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One JSON object per line, with all the fields of [`CollectedBenchStat`]
+    JsonLines,
+    /// One row per record with a header, the latency histogram and the metrics are omitted
+    Csv,
+}
+
+impl OutputFormat {
+    /// Guess the format from the extension of the file: `.csv` is CSV, others are JSON lines
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => OutputFormat::Csv,
+            _ => OutputFormat::JsonLines,
+        }
+    }
+}
+
+const CSV_HEADER: &str = "timestamp_ms,run_id,id,aggregated,phase,throughput,bandwidth,avg_latency,p50_latency,p90_latency,p99_latency,p999_latency,max_latency,num_ops,num_bytes,duration_us,start_time_ms,end_time_ms";
+
+/// One record of the output file
+#[derive(Serialize)]
+struct FileRecord<'a> {
+    timestamp_ms: u64,
+    run_id: &'a str,
+    aggregated: bool,
+    #[serde(flatten)]
+    stat: &'a CollectedBenchStat,
+}
+
+/// A file that the reports are written to, see the module doc for the records.
+pub struct StatFile {
+    writer: LineWriter<File>,
+    format: OutputFormat,
+    run_id: String,
+}
+
+impl StatFile {
+    /// Create (or truncate) the output file, the format is guessed from the extension.
+    /// The run id is generated from the current time.
+    pub fn create<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let run_id = format!("run-{}", unix_time_ms());
+        Self::create_with_run_id(path, &run_id)
+    }
+
+    /// Create (or truncate) the output file with a given run id
+    pub fn create_with_run_id<P: AsRef<Path>>(path: P, run_id: &str) -> std::io::Result<Self> {
+        let format = OutputFormat::from_path(path.as_ref());
+        Self::create_with_format(path, run_id, format)
+    }
+
+    /// Create (or truncate) the output file with a given run id and format
+    pub fn create_with_format<P: AsRef<Path>>(
+        path: P,
+        run_id: &str,
+        format: OutputFormat,
+    ) -> std::io::Result<Self> {
+        let mut writer = LineWriter::new(File::create(path)?);
+        if format == OutputFormat::Csv {
+            writeln!(writer, "{}", CSV_HEADER)?;
+        }
+        Ok(Self {
+            writer,
+            format,
+            run_id: run_id.to_string(),
+        })
+    }
+
+    /// The run id of the records
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Write one record, `aggregated` marks the aggregation of all the reporters
+    pub fn write(&mut self, stat: &CollectedBenchStat, aggregated: bool) -> std::io::Result<()> {
+        let record = FileRecord {
+            timestamp_ms: unix_time_ms(),
+            run_id: &self.run_id,
+            aggregated,
+            stat,
+        };
+        match self.format {
+            OutputFormat::JsonLines => {
+                serde_json::to_writer(&mut self.writer, &record)?;
+                writeln!(self.writer)
+            }
+            OutputFormat::Csv => writeln!(self.writer, "{}", csv_row(&record)),
+        }
+    }
+}
+
+/// A reporter that writes each report of the inner reporter to a file
+pub struct FileReporter<R: BenchReporter> {
+    inner: R,
+    output: StatFile,
+}
+
+impl<R> FileReporter<R>
+where
+    R: BenchReporter,
+{
+    /// Create a file reporter writing to `path`, the format is guessed from the extension
+    pub fn new<P: AsRef<Path>>(path: P, reporter: R) -> std::io::Result<Self> {
+        Ok(Self::new_with_output(StatFile::create(path)?, reporter))
+    }
+
+    /// Create a file reporter writing to an opened output file
+    pub fn new_with_output(output: StatFile, reporter: R) -> Self {
+        Self {
+            inner: reporter,
+            output,
+        }
+    }
+}
+
+impl<R> BenchReporter for FileReporter<R>
+where
+    R: BenchReporter,
+{
+    fn set_phase(&mut self, phase: BenchPhase) {
+        self.inner.set_phase(phase);
+    }
+
+    fn report_collected_stat(&mut self, stats: &[BenchStat]) -> CollectedBenchStat {
+        let res = self.inner.report_collected_stat(stats);
+        if let Err(e) = self.output.write(&res, false) {
+            warn!("failed to write the report to the output file: {}", e);
+        }
+        res
+    }
+}
+
+fn csv_row(record: &FileRecord) -> String {
+    let stat = record.stat;
+    format!(
+        "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+        record.timestamp_ms,
+        csv_escape(record.run_id),
+        stat.id,
+        record.aggregated,
+        stat.phase,
+        stat.throughput,
+        stat.bandwidth,
+        stat.avg_latency,
+        stat.p50_latency,
+        stat.p90_latency,
+        stat.p99_latency,
+        stat.p999_latency,
+        stat.max_latency,
+        stat.num_ops,
+        stat.num_bytes,
+        stat.duration.as_micros(),
+        stat.start_time_ms,
+        stat.end_time_ms
+    )
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileReporter, OutputFormat, StatFile};
+    use crate::{BenchReporter, BenchStat, SimpleBenchReporter};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("netbencher_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_file_reporter_json_lines() {
+        let path = temp_path("report.jsonl");
+        let output = StatFile::create_with_run_id(&path, "test").unwrap();
+        let mut reporter = FileReporter::new_with_output(output, SimpleBenchReporter::new_with_id(3));
+
+        let mut stat = BenchStat::default();
+        stat.finished_batch_ops(10);
+        reporter.report_collected_stat(&[stat.clone()]);
+        stat.finished_batch_ops(5);
+        reporter.report_collected_stat(&[stat]);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let records: Vec<serde_json::Value> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["run_id"], "test");
+        assert_eq!(records[0]["id"], 3);
+        assert_eq!(records[0]["aggregated"], false);
+        assert_eq!(records[0]["num_ops"], 10);
+        assert_eq!(records[1]["num_ops"], 5);
+        assert!(records[1]["timestamp_ms"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_file_reporter_csv() {
+        let path = temp_path("report.csv");
+        assert_eq!(OutputFormat::from_path(&path), OutputFormat::Csv);
+        let output = StatFile::create_with_run_id(&path, "a,b").unwrap();
+        let mut reporter = FileReporter::new_with_output(output, SimpleBenchReporter::new());
+
+        let mut stat = BenchStat::default();
+        stat.finished_batch_ops(10);
+        reporter.report_collected_stat(&[stat]);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("timestamp_ms,run_id,id,"));
+        let fields: Vec<&str> = lines[1].split(',').collect();
+        // the run id with a comma is quoted
+        assert_eq!(fields[1], "\"a");
+        assert_eq!(fields[2], "b\"");
+        assert_eq!(fields[5], "measure");
+        assert_eq!(lines[0].split(',').count() + 1, fields.len());
+    }
+}
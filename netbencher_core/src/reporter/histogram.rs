@@ -0,0 +1,313 @@
+//! A HDR-style latency histogram with log-linear buckets.
+//!
+//! Values (in nanoseconds) below `2^SUB_BUCKET_BITS` are recorded exactly.
+//! Larger values fall into one of `2^SUB_BUCKET_BITS` linear sub-buckets of their power-of-two range,
+//! so the relative error of any reported value is bounded by `2^-SUB_BUCKET_BITS` (about 3%).
+//!
+//! Recording is a shift plus an array increment, so workers can afford to record every op.
+//! Histograms of different workers (or different machines) can be merged losslessly by adding the counters.
+use std::ops;
+
+use serde_derive::{Deserialize, Serialize};
+
+const SUB_BUCKET_BITS: u32 = 5;
+const SUB_BUCKET_COUNT: usize = 1 << SUB_BUCKET_BITS;
+/// Values >= 2^MAX_EXPONENT ns (about 18 minutes) are clamped into the last bucket
+const MAX_EXPONENT: u32 = 40;
+pub(super) const NUM_BUCKETS: usize = (MAX_EXPONENT - SUB_BUCKET_BITS + 1) as usize * SUB_BUCKET_COUNT;
+
+/// The index of the bucket that a value (in nanoseconds) falls into
+#[inline]
+pub(super) fn bucket_index_of(value: u64) -> usize {
+    if value < SUB_BUCKET_COUNT as u64 {
+        return value as usize;
+    }
+    let value = std::cmp::min(value, (1u64 << MAX_EXPONENT) - 1);
+    let exponent = 63 - value.leading_zeros();
+    let shift = exponent - SUB_BUCKET_BITS;
+    let sub = (value >> shift) as usize - SUB_BUCKET_COUNT;
+    (shift as usize + 1) * SUB_BUCKET_COUNT + sub
+}
+
+/// A mergeable latency histogram, values are recorded in nanoseconds.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(into = "SparseHistogram", from = "SparseHistogram")]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    total: u64,
+    sum: u64,
+    max: u64,
+}
+
+/// The wire format of [`LatencyHistogram`]: only non-empty buckets are kept
+#[derive(Serialize, Deserialize)]
+struct SparseHistogram {
+    buckets: Vec<(u32, u64)>,
+    sum: u64,
+    max: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; NUM_BUCKETS],
+            total: 0,
+            sum: 0,
+            max: 0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    /// Create an empty histogram
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a histogram from the raw bucket counters
+    pub(super) fn from_raw(counts: Vec<u64>, sum: u64, max: u64) -> Self {
+        debug_assert_eq!(counts.len(), NUM_BUCKETS);
+        Self {
+            total: counts.iter().sum(),
+            counts,
+            sum,
+            max,
+        }
+    }
+
+    #[inline]
+    fn index_of(value: u64) -> usize {
+        bucket_index_of(value)
+    }
+
+    /// The representative value (the middle of the bucket range) of a bucket
+    #[inline]
+    fn value_of(index: usize) -> u64 {
+        if index < SUB_BUCKET_COUNT {
+            return index as u64;
+        }
+        let shift = (index / SUB_BUCKET_COUNT - 1) as u32;
+        let sub = (index % SUB_BUCKET_COUNT) as u64;
+        ((SUB_BUCKET_COUNT as u64 + sub) << shift) + ((1u64 << shift) >> 1)
+    }
+
+    /// The highest value of a bucket range
+    #[inline]
+    fn highest_value_of(index: usize) -> u64 {
+        if index < SUB_BUCKET_COUNT {
+            return index as u64;
+        }
+        let shift = (index / SUB_BUCKET_COUNT - 1) as u32;
+        let sub = (index % SUB_BUCKET_COUNT) as u64;
+        ((SUB_BUCKET_COUNT as u64 + sub + 1) << shift) - 1
+    }
+
+    /// Record one latency sample (in nanoseconds)
+    #[inline]
+    pub fn record(&mut self, value_ns: u64) {
+        self.record_n(value_ns, 1);
+    }
+
+    /// Record `count` samples with the same latency (in nanoseconds)
+    #[inline]
+    pub fn record_n(&mut self, value_ns: u64, count: u64) {
+        // an empty batch, e.g., a poll without completions, is not a sample of the max
+        if count == 0 {
+            return;
+        }
+        self.counts[Self::index_of(value_ns)] += count;
+        self.total += count;
+        self.sum += value_ns * count;
+        self.max = std::cmp::max(self.max, value_ns);
+    }
+
+    /// Merge the samples of another histogram into this one
+    pub fn merge(&mut self, other: &Self) {
+        for (c, o) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *c += o;
+        }
+        self.total += other.total;
+        self.sum += other.sum;
+        self.max = std::cmp::max(self.max, other.max);
+    }
+
+    /// Clear all the recorded samples
+    pub fn reset(&mut self) {
+        self.counts.iter_mut().for_each(|c| *c = 0);
+        self.total = 0;
+        self.sum = 0;
+        self.max = 0;
+    }
+
+    /// Number of samples recorded
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    /// Whether no sample has been recorded
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// The largest sample recorded (in nanoseconds)
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// The mean of the samples (in nanoseconds), 0 if empty
+    pub fn mean(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.sum as f64 / self.total as f64
+    }
+
+    /// The value (in nanoseconds) below which `quantile` (in [0, 1]) of the samples fall, 0 if empty
+    pub fn value_at_quantile(&self, quantile: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let quantile = quantile.clamp(0.0, 1.0);
+        let target = std::cmp::max(1, (quantile * self.total as f64).ceil() as u64);
+
+        let mut seen = 0;
+        for (i, c) in self.counts.iter().enumerate() {
+            seen += c;
+            if seen >= target {
+                // never report beyond the true max
+                return std::cmp::min(Self::value_of(i), self.max);
+            }
+        }
+        self.max
+    }
+}
+
+impl ops::Sub for LatencyHistogram {
+    type Output = Self;
+
+    /// Samples recorded in `self` but not in `other`, assuming `other` is an earlier copy of `self`.
+    /// The max can not be subtracted, so it is the top of the highest non-empty bucket left,
+    /// clamped to the max of `self` (i.e., within the bucket error of the true max).
+    fn sub(mut self, other: Self) -> Self {
+        for (c, o) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *c -= o;
+        }
+        self.total -= other.total;
+        self.sum -= other.sum;
+        self.max = match self.counts.iter().rposition(|c| *c != 0) {
+            Some(i) => std::cmp::min(Self::highest_value_of(i), self.max),
+            None => 0,
+        };
+        self
+    }
+}
+
+impl From<LatencyHistogram> for SparseHistogram {
+    fn from(h: LatencyHistogram) -> Self {
+        Self {
+            buckets: h
+                .counts
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c != 0)
+                .map(|(i, c)| (i as u32, *c))
+                .collect(),
+            sum: h.sum,
+            max: h.max,
+        }
+    }
+}
+
+impl From<SparseHistogram> for LatencyHistogram {
+    fn from(s: SparseHistogram) -> Self {
+        let mut h = Self::default();
+        for (i, c) in s.buckets {
+            if let Some(slot) = h.counts.get_mut(i as usize) {
+                *slot += c;
+                h.total += c;
+            }
+        }
+        h.sum = s.sum;
+        h.max = s.max;
+        h
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LatencyHistogram;
+
+    #[test]
+    fn test_histogram_bucket_error() {
+        for v in [0u64, 1, 31, 32, 33, 100, 1000, 4096, 123_456, 10_000_000] {
+            let mut h = LatencyHistogram::new();
+            h.record(v);
+            let reported = LatencyHistogram::value_of(LatencyHistogram::index_of(v));
+            let err = (reported as f64 - v as f64).abs();
+            assert!(err <= v as f64 / 32.0, "value {} reported as {}", v, reported);
+            assert_eq!(h.value_at_quantile(0.5), std::cmp::min(reported, v));
+        }
+    }
+
+    #[test]
+    fn test_histogram_quantiles() {
+        let mut h = LatencyHistogram::new();
+        for v in 1..=1000 {
+            h.record(v);
+        }
+        assert_eq!(h.count(), 1000);
+        assert_eq!(h.max(), 1000);
+        assert!((h.mean() - 500.5).abs() < 1e-9);
+
+        let p50 = h.value_at_quantile(0.5) as f64;
+        let p99 = h.value_at_quantile(0.99) as f64;
+        assert!((p50 - 500.0).abs() <= 500.0 / 32.0);
+        assert!((p99 - 990.0).abs() <= 990.0 / 32.0);
+        assert_eq!(h.value_at_quantile(1.0), 1000);
+    }
+
+    #[test]
+    fn test_histogram_merge_and_sub() {
+        let mut a = LatencyHistogram::new();
+        let mut b = LatencyHistogram::new();
+        a.record_n(100, 10);
+        b.record_n(10_000, 10);
+
+        let before = a.clone();
+        a.merge(&b);
+        assert_eq!(a.count(), 20);
+        assert_eq!(a.max(), 10_000);
+        assert!(a.value_at_quantile(0.99) > 9_000);
+
+        let gap = a - before;
+        assert_eq!(gap.count(), 10);
+        assert!(gap.value_at_quantile(0.5) > 9_000);
+        assert_eq!(gap.max(), 10_000);
+
+        // an outlier before is not the max of the samples after it
+        let mut a = LatencyHistogram::new();
+        a.record(1_000_000);
+        let before = a.clone();
+        a.record_n(1000, 10);
+        let gap = a - before.clone();
+        assert!(gap.max() < before.max());
+        assert!(gap.max().abs_diff(1000) <= 1000 / 32, "max: {}", gap.max());
+        assert!(gap.value_at_quantile(0.999) <= gap.max());
+        assert_eq!((before.clone() - before).max(), 0);
+
+        let mut h = LatencyHistogram::new();
+        h.record_n(5000, 0);
+        assert!(h.is_empty());
+        assert_eq!(h.max(), 0);
+    }
+
+    #[test]
+    fn test_histogram_serde() {
+        let mut h = LatencyHistogram::new();
+        h.record_n(250, 3);
+        h.record(1 << 50);
+        let encoded = serde_json::to_string(&h).unwrap();
+        let decoded: LatencyHistogram = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(h, decoded);
+    }
+}
@@ -0,0 +1,105 @@
+//! User-defined metrics recorded by the workers besides the finished ops,
+//! e.g., bytes moved, empty CQ polls, doorbells flushed, retransmits or errors.
+//!
+//! Metrics are registered at the [`crate::BenchRunner`] before it runs (see [`crate::BenchRunner::register_counter`]),
+//! which returns a [`MetricId`] for the workers to update the metric through their [`super::BenchStatWriter`].
+//! There are two kinds of metrics:
+//! - A counter only increases, and the reporters report how much it increases during each period.
+//! - A gauge is set to the latest value, and the reporters report the latest value.
+//!
+//! Both kinds are summed up across workers and across reporters.
+use std::collections::BTreeMap;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// The kind of a registered metric
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MetricKind {
+    /// A monotonically increasing count
+    Counter,
+    /// A value that can go up and down
+    Gauge,
+}
+
+/// The description of a registered metric
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct MetricDesc {
+    pub name: String,
+    pub kind: MetricKind,
+}
+
+/// The handle of a registered metric, used by workers to update the metric
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MetricId(pub(crate) usize);
+
+/// The value of a metric of a worker
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Metric {
+    /// The accumulated count
+    Counter(u64),
+    /// The latest value
+    Gauge(f64),
+}
+
+impl Metric {
+    /// Merge the same metric of another worker into this one
+    pub fn merge(&mut self, other: &Self) {
+        match (self, other) {
+            (Metric::Counter(a), Metric::Counter(b)) => *a += b,
+            (Metric::Gauge(a), Metric::Gauge(b)) => *a += b,
+            // mismatched kinds of the same name, keep ours
+            _ => {}
+        }
+    }
+}
+
+/// The reported value of a metric during a period
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CollectedMetric {
+    /// How much a counter increases during a period
+    Counter {
+        /// The increased count
+        total: u64,
+        /// The increased count per second
+        rate: f64,
+    },
+    /// The latest value of a gauge
+    Gauge(f64),
+}
+
+impl CollectedMetric {
+    /// Merge the same metric of another reporter into this one
+    pub fn merge(&mut self, other: &Self) {
+        match (self, other) {
+            (
+                CollectedMetric::Counter { total, rate },
+                CollectedMetric::Counter {
+                    total: other_total,
+                    rate: other_rate,
+                },
+            ) => {
+                *total += other_total;
+                *rate += other_rate;
+            }
+            (CollectedMetric::Gauge(a), CollectedMetric::Gauge(b)) => *a += b,
+            // mismatched kinds of the same name, keep ours
+            _ => {}
+        }
+    }
+}
+
+/// Merge metrics of the same names
+pub(crate) fn merge_metrics<M: Clone>(
+    to: &mut BTreeMap<String, M>,
+    from: &BTreeMap<String, M>,
+    merge: impl Fn(&mut M, &M),
+) {
+    for (name, m) in from {
+        match to.get_mut(name) {
+            Some(old) => merge(old, m),
+            None => {
+                to.insert(name.clone(), m.clone());
+            }
+        }
+    }
+}
@@ -7,23 +7,19 @@
 //! Example usage of [`SimpleBenchReporter`]:
 //!
 //! ```
-//! #![feature(get_mut_unchecked)]
-
 //! extern crate netbencher_core;
 //!
-//! use std::sync::Arc;
-//!
 //! use netbencher_core::{BenchRunner, SimpleBenchReporter};
 //!
 //! fn main() {    
-//!     let mut runner = BenchRunner::new(1);
+//!     let runner = BenchRunner::new(1);
 //!     runner.run(
 //!         // The evaluated function will increase the statics per second
 //!         |worker_id, runner, mut stats, _| {
 //!             println!("Worker {} started", worker_id);
 //!             while runner.running() {
 //!                 std::thread::sleep(std::time::Duration::from_secs(1));
-//!                 unsafe { Arc::get_mut_unchecked(&mut stats).finished_one_op() };
+//!                 stats.finished_one_op();
 //!             }
 //!         },
 //!         (),
@@ -42,18 +38,57 @@
 //!
 //! ```
 //!
+use std::collections::BTreeMap;
+use std::future::Future;
 use std::ops;
-use std::sync::Arc;
+use std::pin::Pin;
+use std::time::Duration;
 
 use serde_derive::{Deserialize, Serialize};
 
+use crate::BenchPhase;
+
 mod simple_reporter;
 pub use simple_reporter::SimpleBenchReporter;
 
 mod coordinated_reporter;
 pub use coordinated_reporter::{CoordinatedReporter, CoordinatedReporterMaster};
 
+mod file_reporter;
+pub use file_reporter::{FileReporter, OutputFormat, StatFile};
+
+mod prometheus_reporter;
+pub use prometheus_reporter::{PrometheusExporter, PrometheusReporter};
+
+mod tee_reporter;
+pub use tee_reporter::TeeReporter;
+
+mod smoothing_reporter;
+pub use smoothing_reporter::{Smoothing, SmoothingReporter};
+
+mod threshold_reporter;
+pub use threshold_reporter::ThresholdReporter;
+
+mod histogram;
+pub use histogram::LatencyHistogram;
+
+mod breakdown;
+pub use breakdown::WorkerThroughput;
+pub(crate) use breakdown::{breakdown_of, check_workers, spread};
+
+mod metric;
+pub use metric::{CollectedMetric, Metric, MetricId, MetricKind};
+pub(crate) use metric::MetricDesc;
+use metric::merge_metrics;
+
+mod summary;
+pub use summary::{BenchSummary, ThroughputSummary};
+
+mod transport;
 
+mod shared_stat;
+pub use shared_stat::BenchStatWriter;
+pub(crate) use shared_stat::SharedBenchStat;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 struct AvgRdtsc {
@@ -61,25 +96,44 @@ struct AvgRdtsc {
     pub cnt_num: i64,
 }
 
-/// BenchStat is a single stat that is reported by a worker
+/// BenchStat is a snapshot of the stat that is reported by a worker
 /// It records the following things:
-/// > 1. num ops finished during this period
-/// > 2. latency of each op
+/// 1. num ops (and bytes) finished during this period
+/// 2. latency of each op (see [`LatencyHistogram`])
+/// 3. user-registered metrics (see [`Metric`])
+///
 /// etc.
-#[derive(Clone, Copy, Debug, PartialEq)]
-#[repr(align(128))]
+///
+/// Workers record the stat through their [`BenchStatWriter`],
+/// and the reporters get the snapshots of all workers.
+#[derive(Clone, Debug, PartialEq)]
 pub struct BenchStat {
     /// The number of ops finished during this period
     pub num_ops_finished: u64,
 
+    /// The number of bytes transferred by the finished ops during this period
+    pub num_bytes: u64,
+
+    /// The latency samples recorded by the worker
+    pub latency: LatencyHistogram,
+
+    /// The user-registered metrics, indexed by their names
+    pub metrics: BTreeMap<String, Metric>,
+
     /// The average rdtsc value of ops reported
     avg_rdtsc: AvgRdtsc,
+
+    /// The core the worker is pinned to, if any (see [`crate::BenchRunner::pin_workers`])
+    pub core: Option<usize>,
 }
 
 impl BenchStat {
     /// Reset the stat
     pub fn reset(&mut self) {
         self.num_ops_finished = 0;
+        self.num_bytes = 0;
+        self.latency.reset();
+        self.metrics.clear();
         self.avg_rdtsc = AvgRdtsc {value: 0, cnt_num: 0};
     }
 
@@ -93,36 +147,116 @@ impl BenchStat {
         self.num_ops_finished += num_ops;
     }
 
+    /// Mark the stat that some bytes are transferred
+    pub fn transferred_bytes(&mut self, num_bytes: u64) {
+        self.num_bytes += num_bytes;
+    }
+
+    /// Record the latency of one op (in nanoseconds)
+    #[inline]
+    pub fn record_latency_ns(&mut self, latency_ns: u64) {
+        self.latency.record(latency_ns);
+    }
+
+    /// Record the same latency (in nanoseconds) for a batch of ops
+    #[inline]
+    pub fn record_batch_latency_ns(&mut self, latency_ns: u64, num_ops: u64) {
+        self.latency.record_n(latency_ns, num_ops);
+    }
+
+    /// Record the latency of one op
+    #[inline]
+    pub fn record_latency(&mut self, latency: Duration) {
+        self.record_latency_ns(latency.as_nanos() as u64);
+    }
+
     /// Record the average rdtsc for each op
     pub fn record_avg_rdtsc(&mut self, num: i64) {
         self.avg_rdtsc.cnt_num += 1;
         self.avg_rdtsc.value += 
             ((num - self.avg_rdtsc.value as i64) /self.avg_rdtsc.cnt_num) as u64;
     }
+
+    /// Merge the stat of another worker into this one, the core of `self` is kept
+    pub fn merge(&mut self, other: &Self) {
+        self.num_ops_finished += other.num_ops_finished;
+        self.num_bytes += other.num_bytes;
+        self.latency.merge(&other.latency);
+        merge_metrics(&mut self.metrics, &other.metrics, Metric::merge);
+    }
 }
 
 impl Default for BenchStat {
     fn default() -> Self {
         Self {
             num_ops_finished: 0,
+            num_bytes: 0,
+            latency: LatencyHistogram::default(),
+            metrics: BTreeMap::new(),
             avg_rdtsc: AvgRdtsc {value: 0, cnt_num: 0},
+            core: None,
         }
     }
 }
 
 /// A collection of BenchStat to transform it to a user-readable format
 /// Basically, we care about the following stuffs:
-/// 1. throughput
+/// 1. throughput (and bandwidth)
 /// 2. average latency
-/// 3. 99th latency (TBD)
-#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+/// 3. tail latencies (50th, 90th, 99th, 99.9th and max)
+///
+/// All the latencies are in microseconds.
+/// The raw latency samples and the op count are carried along,
+/// so that stats from different reporters can be merged (see [`CollectedBenchStat::merge`]).
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct CollectedBenchStat {
     /// The number of ops finished during a period
     pub throughput: f64,
+    /// The goodput (in Gbps) of the ops finished during a period
+    #[serde(default)]
+    pub bandwidth: f64,
     /// The average latency of all ops
     pub avg_latency: f64,
+    /// The 50th latency of all ops
+    pub p50_latency: f64,
+    /// The 90th latency of all ops
+    pub p90_latency: f64,
     /// The 99th latency of all ops
     pub p99_latency: f64,
+    /// The 99.9th latency of all ops
+    pub p999_latency: f64,
+    /// The max latency of all ops
+    pub max_latency: f64,
+
+    /// The number of ops finished during a period
+    #[serde(default)]
+    pub num_ops: u64,
+    /// The number of bytes transferred during a period
+    #[serde(default)]
+    pub num_bytes: u64,
+    /// The latency samples recorded during a period
+    #[serde(default)]
+    pub latency: LatencyHistogram,
+    /// The user-registered metrics during a period, indexed by their names
+    #[serde(default)]
+    pub metrics: BTreeMap<String, CollectedMetric>,
+    /// The phase of the run during a period
+    #[serde(default)]
+    pub phase: BenchPhase,
+    /// The length of the period
+    #[serde(default)]
+    pub duration: Duration,
+    /// The wall-clock time (milliseconds since the UNIX epoch) when the period starts
+    #[serde(default)]
+    pub start_time_ms: u64,
+    /// The wall-clock time (milliseconds since the UNIX epoch) when the period ends.
+    /// The reports received by the master are in the clock of the master, see [`CoordinatedReporter::clock_offset_us`].
+    #[serde(default)]
+    pub end_time_ms: u64,
+    /// The throughput of each worker during a period, if the reporter breaks it down
+    /// (see [`SimpleBenchReporter::with_worker_breakdown`])
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub workers: Vec<WorkerThroughput>,
 
     /// The id of the stats
     pub id: usize,
@@ -132,79 +266,428 @@ impl Default for CollectedBenchStat {
     fn default() -> Self {
         Self {
             throughput: 0.0,
+            bandwidth: 0.0,
             avg_latency: 0.0,
+            p50_latency: 0.0,
+            p90_latency: 0.0,
             p99_latency: 0.0,
+            p999_latency: 0.0,
+            max_latency: 0.0,
+            num_ops: 0,
+            num_bytes: 0,
+            latency: LatencyHistogram::default(),
+            metrics: BTreeMap::new(),
+            phase: BenchPhase::default(),
+            duration: Duration::ZERO,
+            start_time_ms: 0,
+            end_time_ms: 0,
+            workers: Vec::new(),
             id: 0,
         }
     }
 }
 
 impl CollectedBenchStat {
+    /// Collect the stat finished during a period of `duration`
+    pub fn from_stat(stat: BenchStat, period: Duration) -> Self {
+        // microseconds passed
+        let duration = period.as_micros() as f64;
+        // mops
+        let throughput = stat.num_ops_finished as f64 / duration;
+        // gbps
+        let bandwidth = (stat.num_bytes * 8) as f64 / duration / 1000.0;
+        // microseconds, only an estimation if the workers record no latency
+        let avg_latency = if stat.num_ops_finished == 0 {
+            0.0
+        } else {
+            duration / stat.num_ops_finished as f64
+        };
+
+        let metrics = stat
+            .metrics
+            .into_iter()
+            .map(|(name, m)| {
+                let m = match m {
+                    Metric::Counter(total) => CollectedMetric::Counter {
+                        total,
+                        // per second
+                        rate: total as f64 / duration * 1e6,
+                    },
+                    Metric::Gauge(v) => CollectedMetric::Gauge(v),
+                };
+                (name, m)
+            })
+            .collect();
+
+        let mut res = Self {
+            throughput,
+            bandwidth,
+            avg_latency,
+            num_ops: stat.num_ops_finished,
+            num_bytes: stat.num_bytes,
+            metrics,
+            duration: period,
+            ..Default::default()
+        };
+        res.set_latency(stat.latency);
+        res
+    }
+
     /// Reset the stat
     pub fn reset(&mut self) {
         self.throughput = 0.0;
+        self.bandwidth = 0.0;
         self.avg_latency = 0.0;
+        self.p50_latency = 0.0;
+        self.p90_latency = 0.0;
         self.p99_latency = 0.0;
+        self.p999_latency = 0.0;
+        self.max_latency = 0.0;
+        self.num_ops = 0;
+        self.num_bytes = 0;
+        self.latency.reset();
+        self.metrics.clear();
+        self.duration = Duration::ZERO;
+        self.start_time_ms = 0;
+        self.end_time_ms = 0;
+        self.workers.clear();
     }
+
+    /// Fill the latency fields with the samples in the histogram.
+    /// If no sample is recorded, the latency fields are left untouched.
+    pub fn set_latency(&mut self, latency: LatencyHistogram) {
+        self.latency = latency;
+        if self.latency.is_empty() {
+            return;
+        }
+        // ns -> µs
+        let to_us = |ns: u64| ns as f64 / 1000.0;
+        self.avg_latency = self.latency.mean() / 1000.0;
+        self.p50_latency = to_us(self.latency.value_at_quantile(0.5));
+        self.p90_latency = to_us(self.latency.value_at_quantile(0.9));
+        self.p99_latency = to_us(self.latency.value_at_quantile(0.99));
+        self.p999_latency = to_us(self.latency.value_at_quantile(0.999));
+        self.max_latency = to_us(self.latency.max());
+    }
+
+    /// The spread of the throughputs of the workers, i.e., `(max - min) / mean`,
+    /// or `None` if the report has no breakdown of at least two workers
+    pub fn worker_spread(&self) -> Option<f64> {
+        spread(self.workers.iter().map(|w| w.throughput))
+    }
+
+    /// Merge the stat of another reporter into this one.
+    ///
+    /// Throughputs are summed up, the average latency is weighted by the number of ops of each reporter,
+    /// and the percentiles are re-computed from the merged latency samples.
+    /// The id of `self` is kept, while the phase becomes the one out of the measured window if any.
+    /// The per-worker breakdown is dropped, since the worker ids of different reporters collide.
+    pub fn merge(&mut self, other: &Self) {
+        if other.phase != BenchPhase::Measure {
+            self.phase = other.phase;
+        }
+        let num_ops = self.num_ops + other.num_ops;
+        if num_ops != 0 {
+            self.avg_latency = (self.avg_latency * self.num_ops as f64
+                + other.avg_latency * other.num_ops as f64)
+                / num_ops as f64;
+        }
+        self.throughput += other.throughput;
+        self.bandwidth += other.bandwidth;
+        self.num_ops = num_ops;
+        self.num_bytes += other.num_bytes;
+        // the reporters run in parallel
+        self.duration = std::cmp::max(self.duration, other.duration);
+        self.start_time_ms = match (self.start_time_ms, other.start_time_ms) {
+            (0, t) | (t, 0) => t,
+            (a, b) => std::cmp::min(a, b),
+        };
+        self.end_time_ms = std::cmp::max(self.end_time_ms, other.end_time_ms);
+        merge_metrics(&mut self.metrics, &other.metrics, CollectedMetric::merge);
+        self.workers.clear();
+
+        let mut latency = std::mem::take(&mut self.latency);
+        latency.merge(&other.latency);
+        self.set_latency(latency);
+    }
+}
+
+/// The current wall-clock time in milliseconds since the UNIX epoch
+pub(crate) fn unix_time_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// The current wall-clock time in microseconds since the UNIX epoch
+pub(crate) fn unix_time_us() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
 }
 
 /// BenchReporter is a trait that defines how to report stats collected.
 pub trait BenchReporter {
+    /// Notify the reporter the phase of the run, which will be stamped on the following reports.
+    fn set_phase(&mut self, _phase: BenchPhase) {}
+
     /// Collect the results from the list of BenchStats and collect it to a CollectedBenchStat,
     /// which is a user-readable format.
-    fn report_collected_stat(&mut self, stats: &Vec<Arc<BenchStat>>) -> CollectedBenchStat;
+    fn report_collected_stat(&mut self, stats: &[BenchStat]) -> CollectedBenchStat;
+}
+
+impl<R: BenchReporter + ?Sized> BenchReporter for Box<R> {
+    fn set_phase(&mut self, phase: BenchPhase) {
+        (**self).set_phase(phase);
+    }
+
+    fn report_collected_stat(&mut self, stats: &[BenchStat]) -> CollectedBenchStat {
+        (**self).report_collected_stat(stats)
+    }
 }
 
+/// The future of an async report.
+/// It is boxed so that [`AsyncBenchReporter`] can be used as a trait object.
+pub type ReportFuture<'a> = Pin<Box<dyn Future<Output = CollectedBenchStat> + 'a>>;
+
 /// AsyncBenchReporter is a trait that defines how to report stats collected.
-/// The only difference with [`BenchmarkReporter`] is that it is async.
-pub trait AsyncBenchReporter { 
+/// The only difference with [`BenchReporter`] is that it is async,
+/// e.g., to send the reports over the network (see [`CoordinatedReporter`]).
+///
+/// An implementation wraps its async body in a box:
+///
+/// ```
+/// use netbencher_core::{AsyncBenchReporter, BenchReporter, BenchStat, CollectedBenchStat, ReportFuture, SimpleBenchReporter};
+///
+/// struct PushReporter(SimpleBenchReporter);
+///
+/// impl BenchReporter for PushReporter {
+///     fn report_collected_stat(&mut self, stats: &[BenchStat]) -> CollectedBenchStat {
+///         self.0.report_collected_stat(stats)
+///     }
+/// }
+///
+/// impl AsyncBenchReporter for PushReporter {
+///     fn async_report_collect_stat<'a>(&'a mut self, stats: &'a [BenchStat]) -> ReportFuture<'a> {
+///         Box::pin(async move {
+///             let res = self.0.report_collected_stat(stats);
+///             // push `res` somewhere ...
+///             res
+///         })
+///     }
+/// }
+/// ```
+pub trait AsyncBenchReporter: BenchReporter {
     /// Collect the results from the list of BenchStats and collect it to a CollectedBenchStat,
     /// which is a user-readable format (async version).
-    async fn async_report_collect_stat(&mut self, stats: &Vec<Arc<BenchStat>>) -> CollectedBenchStat;
+    fn async_report_collect_stat<'a>(&'a mut self, stats: &'a [BenchStat]) -> ReportFuture<'a>;
+}
+
+impl<R: AsyncBenchReporter + ?Sized> AsyncBenchReporter for Box<R> {
+    fn async_report_collect_stat<'a>(&'a mut self, stats: &'a [BenchStat]) -> ReportFuture<'a> {
+        (**self).async_report_collect_stat(stats)
+    }
 }
 
 impl ops::Add for CollectedBenchStat {
     type Output = Self;
 
-    fn add(self, other: Self) -> Self {
-        // FIXME: the latency calculation is not so properly here
-        Self {
-            throughput: self.throughput + other.throughput,
-            avg_latency: (self.avg_latency + other.avg_latency) / 2.0,
-            p99_latency: (self.p99_latency + other.p99_latency) / 2.0,
-            id: self.id,
-        }
+    fn add(mut self, other: Self) -> Self {
+        self.merge(&other);
+        self
     }
 }
 
 impl ops::Add for BenchStat {
     type Output = Self;
 
-    fn add(self, other: Self) -> Self {
-        Self {
-            num_ops_finished: self.num_ops_finished + other.num_ops_finished,
-            avg_rdtsc: AvgRdtsc {value: 0, cnt_num: 0},
-        }
+    fn add(mut self, other: Self) -> Self {
+        self.merge(&other);
+        self.avg_rdtsc = AvgRdtsc {value: 0, cnt_num: 0};
+        self
     }
 }
 
 impl ops::Sub for BenchStat {
     type Output = Self;
 
-    fn sub(self, other: Self) -> Self {
+    /// Counters are subtracted, while gauges keep the values of `self`
+    fn sub(mut self, other: Self) -> Self {
+        for (name, m) in self.metrics.iter_mut() {
+            if let (Metric::Counter(a), Some(Metric::Counter(b))) = (m, other.metrics.get(name)) {
+                *a = a.saturating_sub(*b);
+            }
+        }
         Self {
             num_ops_finished: self.num_ops_finished - other.num_ops_finished,
+            num_bytes: self.num_bytes - other.num_bytes,
+            latency: self.latency - other.latency,
+            metrics: self.metrics,
             avg_rdtsc: AvgRdtsc {value: 0, cnt_num: 0},
+            core: self.core,
         }
     }
 }
 
 impl std::fmt::Display for CollectedBenchStat {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.phase != BenchPhase::Measure {
+            write!(f, "[{}] ", self.phase)?;
+        }
         write!(
             f,
-            "@{} Throughput: {:.4} Mops/s, Avg Latency: {:.2} µs",
-            self.id, self.throughput, self.avg_latency
-        )
+            "@{} Throughput: {:.4} Mops/s, Bandwidth: {:.4} Gbps, Avg Latency: {:.2} µs, p50: {:.2} µs, p90: {:.2} µs, p99: {:.2} µs, p99.9: {:.2} µs, max: {:.2} µs",
+            self.id,
+            self.throughput,
+            self.bandwidth,
+            self.avg_latency,
+            self.p50_latency,
+            self.p90_latency,
+            self.p99_latency,
+            self.p999_latency,
+            self.max_latency
+        )?;
+        for (name, m) in &self.metrics {
+            match m {
+                CollectedMetric::Counter { rate, .. } => {
+                    write!(f, ", {}: {:.4} M/s", name, rate / 1e6)?
+                }
+                CollectedMetric::Gauge(v) => write!(f, ", {}: {:.2}", name, v)?,
+            }
+        }
+        if !self.workers.is_empty() {
+            write!(f, ", workers: [")?;
+            for (i, w) in self.workers.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", w)?;
+            }
+            write!(f, "]")?;
+        }
+        Ok(())
+    }
+}
+
+/// A reporter replaying the given throughputs, one per report, to test the reporters wrapping it
+#[cfg(test)]
+pub(crate) struct Replay(pub Vec<f64>);
+
+#[cfg(test)]
+impl BenchReporter for Replay {
+    fn report_collected_stat(&mut self, _stats: &[BenchStat]) -> CollectedBenchStat {
+        CollectedBenchStat {
+            throughput: self.0.remove(0),
+            num_ops: 1,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CollectedBenchStat, LatencyHistogram};
+
+    fn stat_of(id: usize, num_ops: u64, latency_ns: u64) -> CollectedBenchStat {
+        let mut latency = LatencyHistogram::new();
+        latency.record_n(latency_ns, num_ops);
+        let mut stat = CollectedBenchStat {
+            id,
+            throughput: num_ops as f64 / 1e6,
+            num_ops,
+            ..Default::default()
+        };
+        stat.set_latency(latency);
+        stat
+    }
+
+    #[test]
+    fn test_merge_weights_latency_by_ops() {
+        let fast = stat_of(0, 900, 1_000);
+        let slow = stat_of(1, 100, 10_000);
+
+        let res = fast.clone() + slow.clone();
+        assert_eq!(res.num_ops, 1000);
+        assert!((res.avg_latency - 1.9).abs() < 1e-9);
+        // 90% of the ops are fast, so the p90 must not be polluted by the slow client
+        assert!((res.p90_latency - 1.0).abs() <= 1.0 / 32.0);
+        assert!((res.p99_latency - 10.0).abs() <= 10.0 / 32.0);
+        assert_eq!(res.max_latency, 10.0);
+    }
+
+    #[test]
+    fn test_merge_is_order_independent() {
+        let stats = [stat_of(0, 10, 1_000), stat_of(1, 20, 2_000), stat_of(2, 70, 5_000)];
+
+        let mut forward = CollectedBenchStat::default();
+        stats.iter().for_each(|s| forward.merge(s));
+        let mut backward = CollectedBenchStat::default();
+        stats.iter().rev().for_each(|s| backward.merge(s));
+
+        assert_eq!(forward.num_ops, backward.num_ops);
+        assert!((forward.throughput - backward.throughput).abs() < 1e-12);
+        assert!((forward.avg_latency - backward.avg_latency).abs() < 1e-9);
+        assert_eq!(forward.p50_latency, backward.p50_latency);
+        assert_eq!(forward.p99_latency, backward.p99_latency);
+        assert_eq!(forward.latency, backward.latency);
+    }
+
+    #[test]
+    fn test_merge_without_latency_samples() {
+        let mut a = CollectedBenchStat {
+            throughput: 3.0,
+            bandwidth: 1.5,
+            avg_latency: 1.0,
+            num_ops: 300,
+            num_bytes: 300 * 64,
+            ..Default::default()
+        };
+        let b = CollectedBenchStat {
+            throughput: 1.0,
+            bandwidth: 0.5,
+            avg_latency: 5.0,
+            num_ops: 100,
+            num_bytes: 100 * 64,
+            ..Default::default()
+        };
+        a.merge(&b);
+        assert_eq!(a.throughput, 4.0);
+        assert_eq!(a.bandwidth, 2.0);
+        assert_eq!(a.num_bytes, 400 * 64);
+        assert!((a.avg_latency - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_merge_phase() {
+        use crate::BenchPhase;
+
+        let mut a = stat_of(0, 10, 1_000);
+        let mut b = stat_of(1, 10, 1_000);
+        b.phase = BenchPhase::Warmup;
+        a.merge(&b);
+        // any reporter out of the measured window taints the merged stat
+        assert_eq!(a.phase, BenchPhase::Warmup);
+        assert!(a.to_string().starts_with("[warmup] @0"));
+
+        a.merge(&stat_of(2, 10, 1_000));
+        assert_eq!(a.phase, BenchPhase::Warmup);
+    }
+
+    #[test]
+    fn test_merge_times() {
+        let mut a = CollectedBenchStat::default();
+        let mut b = stat_of(1, 10, 1_000);
+        b.start_time_ms = 2_000;
+        b.end_time_ms = 3_000;
+        a.merge(&b);
+        // an empty stat has no times
+        assert_eq!((a.start_time_ms, a.end_time_ms), (2_000, 3_000));
+
+        b.start_time_ms = 1_900;
+        b.end_time_ms = 2_900;
+        a.merge(&b);
+        assert_eq!((a.start_time_ms, a.end_time_ms), (1_900, 3_000));
     }
 }
@@ -0,0 +1,372 @@
+//! Serve the latest reports on an HTTP `/metrics` endpoint in the Prometheus text format,
+//! so that long runs can be watched on the dashboards.
+//!
+//! The [`PrometheusExporter`] runs a tiny HTTP server on a background thread.
+//! It is fed by a [`PrometheusReporter`] at the clients, which exports the stats of the runner and of each worker,
+//! or by the [`super::CoordinatedReporterMaster`] (see `set_exporter`), which exports the stats of each client
+//! and the aggregation of the whole cluster (with the label `reporter="cluster"`).
+//!
+//! Example usage:
+//!
+//! ```no_run
+//! use netbencher_core::{BenchRunner, PrometheusExporter, PrometheusReporter, SimpleBenchReporter};
+//!
+//! let runner = BenchRunner::new(1);
+//! runner.run(|_, runner, mut stat, _| {
+//!     while runner.running() {
+//!         stat.finished_one_op();
+//!     }
+//! }, ());
+//!
+//! let exporter = PrometheusExporter::bind("0.0.0.0:9100".parse().unwrap()).unwrap();
+//! let mut reporter = PrometheusReporter::new(exporter, SimpleBenchReporter::new());
+//! for _ in 0..10 {
+//!     std::thread::sleep(std::time::Duration::from_secs(1));
+//!     runner.report(&mut reporter);
+//! }
+//! runner.stop().unwrap();
+//! ```
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use log::warn;
+
+use super::{BenchReporter, BenchStat, CollectedBenchStat, CollectedMetric, Metric};
+use crate::BenchPhase;
+
+/// The label of the aggregated stats of all the reporters
+const CLUSTER_LABEL: &str = "cluster";
+
+/// An exported metric: the name, the help text and how to get the value
+type MetricOf<S, V> = (&'static str, &'static str, fn(&S) -> V);
+
+/// The latest stats to export
+#[derive(Default)]
+struct ExportedStats {
+    // reporter label -> the latest report
+    reports: BTreeMap<String, CollectedBenchStat>,
+    // (reporter label, worker id) -> the accumulated stat of the worker
+    workers: BTreeMap<(String, usize), BenchStat>,
+}
+
+/// An HTTP server exporting the latest reports on `/metrics`.
+///
+/// The server thread is stopped when the exporter is dropped.
+pub struct PrometheusExporter {
+    stats: Arc<Mutex<ExportedStats>>,
+    local_addr: SocketAddr,
+    running: Arc<AtomicBool>,
+    handler: Option<JoinHandle<()>>,
+}
+
+impl PrometheusExporter {
+    /// Start serving on `addr`, use port 0 to pick a free port (see [`PrometheusExporter::local_addr`])
+    pub fn bind(addr: SocketAddr) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let stats: Arc<Mutex<ExportedStats>> = Default::default();
+        let running = Arc::new(AtomicBool::new(true));
+
+        let (server_stats, server_running) = (stats.clone(), running.clone());
+        let handler = std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !server_running.load(Ordering::Relaxed) {
+                    break;
+                }
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = serve(stream, &server_stats) {
+                            warn!("failed to serve the metrics: {}", e);
+                        }
+                    }
+                    Err(e) => warn!("failed to accept a metrics connection: {}", e),
+                }
+            }
+        });
+
+        Ok(Self {
+            stats,
+            local_addr,
+            running,
+            handler: Some(handler),
+        })
+    }
+
+    /// The address the server listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Export the latest report of a reporter
+    pub fn update_report(&self, stat: &CollectedBenchStat) {
+        self.update(stat.id.to_string(), stat);
+    }
+
+    /// Export the latest aggregated report of all the reporters
+    pub fn update_cluster(&self, stat: &CollectedBenchStat) {
+        self.update(CLUSTER_LABEL.to_string(), stat);
+    }
+
+    fn update(&self, label: String, stat: &CollectedBenchStat) {
+        self.stats.lock().unwrap().reports.insert(label, stat.clone());
+    }
+
+    /// Export the accumulated stats of the workers of a reporter
+    pub fn update_workers(&self, reporter_id: usize, stats: &[BenchStat]) {
+        let mut exported = self.stats.lock().unwrap();
+        for (i, stat) in stats.iter().enumerate() {
+            exported
+                .workers
+                .insert((reporter_id.to_string(), i), stat.clone());
+        }
+    }
+
+    /// Render the exported stats in the Prometheus text format
+    pub fn render(&self) -> String {
+        render(&self.stats.lock().unwrap())
+    }
+}
+
+impl Drop for PrometheusExporter {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        // wake up the server thread blocked on accept
+        let _ = TcpStream::connect(self.local_addr);
+        if let Some(handler) = self.handler.take() {
+            let _ = handler.join();
+        }
+    }
+}
+
+fn serve(mut stream: TcpStream, stats: &Mutex<ExportedStats>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    // read until the end of the headers, the request may come in several segments
+    let mut buf = [0u8; 4096];
+    let mut n = 0;
+    while n < buf.len() && !buf[..n].windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut buf[n..])?;
+        if read == 0 {
+            break;
+        }
+        n += read;
+    }
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+
+    let (status, body) = if path == "/metrics" {
+        ("200 OK", render(&stats.lock().unwrap()))
+    } else {
+        ("404 Not Found", String::from("not found\n"))
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Escape a label value of the text format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn render(stats: &ExportedStats) -> String {
+    let mut out = String::new();
+
+    let report_gauges: [MetricOf<CollectedBenchStat, f64>; 4] = [
+        ("netbencher_throughput_mops", "Throughput of the last interval (Mops/s)", |s| s.throughput),
+        ("netbencher_bandwidth_gbps", "Bandwidth of the last interval (Gbps)", |s| s.bandwidth),
+        ("netbencher_avg_latency_us", "Average latency of the last interval (µs)", |s| s.avg_latency),
+        ("netbencher_measuring", "Whether the last interval is in the measured window", |s| {
+            (s.phase == BenchPhase::Measure) as u8 as f64
+        }),
+    ];
+    for (name, help, value) in report_gauges {
+        header(&mut out, name, "gauge", help);
+        for (label, stat) in &stats.reports {
+            let _ = writeln!(out, "{}{{reporter=\"{}\"}} {}", name, escape(label), value(stat));
+        }
+    }
+
+    header(&mut out, "netbencher_latency_us", "gauge", "Latency quantiles of the last interval (µs)");
+    for (label, stat) in &stats.reports {
+        for (quantile, value) in [
+            ("0.5", stat.p50_latency),
+            ("0.9", stat.p90_latency),
+            ("0.99", stat.p99_latency),
+            ("0.999", stat.p999_latency),
+            ("1", stat.max_latency),
+        ] {
+            let _ = writeln!(
+                out,
+                "netbencher_latency_us{{reporter=\"{}\",quantile=\"{}\"}} {}",
+                escape(label),
+                quantile,
+                value
+            );
+        }
+    }
+
+    header(&mut out, "netbencher_metric", "gauge", "User-registered metrics of the last interval (counters are per second)");
+    for (label, stat) in &stats.reports {
+        for (name, m) in &stat.metrics {
+            let value = match m {
+                CollectedMetric::Counter { rate, .. } => *rate,
+                CollectedMetric::Gauge(v) => *v,
+            };
+            let _ = writeln!(
+                out,
+                "netbencher_metric{{reporter=\"{}\",name=\"{}\"}} {}",
+                escape(label),
+                escape(name),
+                value
+            );
+        }
+    }
+
+    let worker_counters: [MetricOf<BenchStat, u64>; 2] = [
+        ("netbencher_worker_ops_total", "Ops finished by each worker", |s| s.num_ops_finished),
+        ("netbencher_worker_bytes_total", "Bytes transferred by each worker", |s| s.num_bytes),
+    ];
+    for (name, help, value) in worker_counters {
+        header(&mut out, name, "counter", help);
+        for ((label, worker), stat) in &stats.workers {
+            let _ = writeln!(
+                out,
+                "{}{{reporter=\"{}\",worker=\"{}\"}} {}",
+                name,
+                escape(label),
+                worker,
+                value(stat)
+            );
+        }
+    }
+
+    header(&mut out, "netbencher_worker_metric", "untyped", "User-registered metrics of each worker");
+    for ((label, worker), stat) in &stats.workers {
+        for (name, m) in &stat.metrics {
+            let value = match m {
+                Metric::Counter(v) => *v as f64,
+                Metric::Gauge(v) => *v,
+            };
+            let _ = writeln!(
+                out,
+                "netbencher_worker_metric{{reporter=\"{}\",worker=\"{}\",name=\"{}\"}} {}",
+                escape(label),
+                worker,
+                escape(name),
+                value
+            );
+        }
+    }
+    out
+}
+
+/// A reporter that exports each report of the inner reporter, and the stats of each worker, to a [`PrometheusExporter`]
+pub struct PrometheusReporter<R: BenchReporter> {
+    inner: R,
+    exporter: PrometheusExporter,
+}
+
+impl<R> PrometheusReporter<R>
+where
+    R: BenchReporter,
+{
+    /// Create a reporter exporting to `exporter`
+    pub fn new(exporter: PrometheusExporter, reporter: R) -> Self {
+        Self {
+            inner: reporter,
+            exporter,
+        }
+    }
+
+    /// The exporter that the reports are exported to
+    pub fn exporter(&self) -> &PrometheusExporter {
+        &self.exporter
+    }
+}
+
+impl<R> BenchReporter for PrometheusReporter<R>
+where
+    R: BenchReporter,
+{
+    fn set_phase(&mut self, phase: BenchPhase) {
+        self.inner.set_phase(phase);
+    }
+
+    fn report_collected_stat(&mut self, stats: &[BenchStat]) -> CollectedBenchStat {
+        let res = self.inner.report_collected_stat(stats);
+        self.exporter.update_report(&res);
+        self.exporter.update_workers(res.id, stats);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    use super::{PrometheusExporter, PrometheusReporter};
+    use crate::{BenchReporter, BenchStat, CollectedBenchStat, SimpleBenchReporter};
+
+    fn scrape(exporter: &PrometheusExporter, path: &str) -> String {
+        let mut stream = TcpStream::connect(exporter.local_addr()).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_prometheus_reporter_scrape() {
+        let exporter = PrometheusExporter::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let mut reporter = PrometheusReporter::new(exporter, SimpleBenchReporter::new_with_id(2));
+
+        let mut worker = BenchStat::default();
+        worker.finished_batch_ops(42);
+        worker.transferred_bytes(42 * 64);
+        worker.record_latency_ns(3_000);
+        reporter.report_collected_stat(&[BenchStat::default(), worker]);
+
+        let response = scrape(reporter.exporter(), "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("# TYPE netbencher_throughput_mops gauge"));
+        assert!(response.contains("netbencher_throughput_mops{reporter=\"2\"}"));
+        assert!(response.contains("netbencher_latency_us{reporter=\"2\",quantile=\"1\"} 3\n"));
+        assert!(response.contains("netbencher_worker_ops_total{reporter=\"2\",worker=\"1\"} 42\n"));
+        assert!(response.contains("netbencher_worker_bytes_total{reporter=\"2\",worker=\"0\"} 0\n"));
+
+        assert!(scrape(reporter.exporter(), "/").starts_with("HTTP/1.1 404"));
+    }
+
+    #[test]
+    fn test_prometheus_cluster_label() {
+        let exporter = PrometheusExporter::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let stat = CollectedBenchStat {
+            throughput: 1.5,
+            ..Default::default()
+        };
+        exporter.update_cluster(&stat);
+        assert!(exporter
+            .render()
+            .contains("netbencher_throughput_mops{reporter=\"cluster\"} 1.5\n"));
+    }
+}
@@ -0,0 +1,278 @@
+//! The storage of the stats shared between a worker and the reporters.
+//!
+//! Each worker exclusively owns a [`BenchStatWriter`], while the reporters take [`BenchStat`] snapshots
+//! through the [`SharedBenchStat`] kept by the runner.
+//! Since there is only one writer per stat, the writer updates each counter with a relaxed load and store
+//! (no read-modify-write), which compiles to the same instructions as a plain increment.
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::histogram::{bucket_index_of, NUM_BUCKETS};
+use super::{AvgRdtsc, BenchStat, LatencyHistogram, Metric, MetricDesc, MetricId, MetricKind};
+
+/// Add `num` to a counter that is only written by one thread
+#[inline(always)]
+fn single_writer_add(counter: &AtomicU64, num: u64) {
+    counter.store(counter.load(Ordering::Relaxed) + num, Ordering::Relaxed);
+}
+
+/// The atomic version of [`LatencyHistogram`], written by one worker and read by the reporters
+struct AtomicLatencyHistogram {
+    counts: Box<[AtomicU64]>,
+    sum: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Default for AtomicLatencyHistogram {
+    fn default() -> Self {
+        Self {
+            counts: (0..NUM_BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+impl AtomicLatencyHistogram {
+    #[inline]
+    fn record_n(&self, value_ns: u64, count: u64) {
+        if count == 0 {
+            return;
+        }
+        single_writer_add(&self.counts[bucket_index_of(value_ns)], count);
+        single_writer_add(&self.sum, value_ns * count);
+        if value_ns > self.max.load(Ordering::Relaxed) {
+            self.max.store(value_ns, Ordering::Relaxed);
+        }
+    }
+
+    fn snapshot(&self) -> LatencyHistogram {
+        LatencyHistogram::from_raw(
+            self.counts.iter().map(|c| c.load(Ordering::Relaxed)).collect(),
+            self.sum.load(Ordering::Relaxed),
+            self.max.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// The stat of one worker, which is shared by the worker ([`BenchStatWriter`]) and the reporters.
+/// It is cache-line padded, so that the counters of different workers never share a cache line.
+#[repr(align(128))]
+#[derive(Default)]
+pub(crate) struct SharedBenchStat {
+    num_ops_finished: AtomicU64,
+    num_bytes: AtomicU64,
+    rdtsc_value: AtomicU64,
+    rdtsc_cnt: AtomicI64,
+    latency: AtomicLatencyHistogram,
+    // counters are stored as is, gauges are stored as the bits of f64
+    metrics: Box<[AtomicU64]>,
+    metric_descs: Arc<[MetricDesc]>,
+    // the core the worker is pinned to
+    core: Option<usize>,
+}
+
+impl SharedBenchStat {
+    /// The number of ops and bytes finished so far, which is cheaper than a snapshot
+    #[inline]
+    pub(crate) fn progress(&self) -> (u64, u64) {
+        (
+            self.num_ops_finished.load(Ordering::Relaxed),
+            self.num_bytes.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Take a snapshot of the current stat
+    pub(crate) fn snapshot(&self) -> BenchStat {
+        BenchStat {
+            num_ops_finished: self.num_ops_finished.load(Ordering::Relaxed),
+            num_bytes: self.num_bytes.load(Ordering::Relaxed),
+            latency: self.latency.snapshot(),
+            metrics: self
+                .metric_descs
+                .iter()
+                .zip(self.metrics.iter())
+                .map(|(desc, m)| {
+                    let value = m.load(Ordering::Relaxed);
+                    let m = match desc.kind {
+                        MetricKind::Counter => Metric::Counter(value),
+                        MetricKind::Gauge => Metric::Gauge(f64::from_bits(value)),
+                    };
+                    (desc.name.clone(), m)
+                })
+                .collect(),
+            avg_rdtsc: AvgRdtsc {
+                value: self.rdtsc_value.load(Ordering::Relaxed),
+                cnt_num: self.rdtsc_cnt.load(Ordering::Relaxed),
+            },
+            core: self.core,
+        }
+    }
+}
+
+/// The handle for a worker to record its stats.
+///
+/// Each worker owns exactly one writer (it is not `Clone`),
+/// while the reporters read the stats concurrently through snapshots.
+pub struct BenchStatWriter {
+    inner: Arc<SharedBenchStat>,
+}
+
+impl BenchStatWriter {
+    /// Create a writer and the shared stat it writes to, with the registered metrics,
+    /// for a worker pinned to `core` (if any)
+    pub(crate) fn new(metric_descs: Arc<[MetricDesc]>, core: Option<usize>) -> (Self, Arc<SharedBenchStat>) {
+        let inner = Arc::new(SharedBenchStat {
+            metrics: metric_descs
+                .iter()
+                .map(|d| match d.kind {
+                    MetricKind::Counter => AtomicU64::new(0),
+                    MetricKind::Gauge => AtomicU64::new(0f64.to_bits()),
+                })
+                .collect(),
+            metric_descs,
+            core,
+            ..Default::default()
+        });
+        (
+            Self {
+                inner: inner.clone(),
+            },
+            inner,
+        )
+    }
+
+    /// Mark the stat that one op is finished
+    #[inline]
+    pub fn finished_one_op(&mut self) {
+        self.finished_batch_ops(1);
+    }
+
+    /// Mark the stat that a batch of ops are finished
+    #[inline]
+    pub fn finished_batch_ops(&mut self, num_ops: u64) {
+        single_writer_add(&self.inner.num_ops_finished, num_ops);
+    }
+
+    /// Mark the stat that some bytes are transferred, e.g., the payload of the finished ops
+    #[inline]
+    pub fn transferred_bytes(&mut self, num_bytes: u64) {
+        single_writer_add(&self.inner.num_bytes, num_bytes);
+    }
+
+    /// Record the latency of one op (in nanoseconds)
+    #[inline]
+    pub fn record_latency_ns(&mut self, latency_ns: u64) {
+        self.inner.latency.record_n(latency_ns, 1);
+    }
+
+    /// Record the same latency (in nanoseconds) for a batch of ops, e.g., the replies received by one poll
+    #[inline]
+    pub fn record_batch_latency_ns(&mut self, latency_ns: u64, num_ops: u64) {
+        self.inner.latency.record_n(latency_ns, num_ops);
+    }
+
+    /// Record the latency of one op
+    #[inline]
+    pub fn record_latency(&mut self, latency: Duration) {
+        self.record_latency_ns(latency.as_nanos() as u64);
+    }
+
+    /// Increase a registered counter by `num`
+    #[inline]
+    pub fn add_counter(&mut self, id: MetricId, num: u64) {
+        debug_assert_eq!(self.inner.metric_descs[id.0].kind, MetricKind::Counter);
+        single_writer_add(&self.inner.metrics[id.0], num);
+    }
+
+    /// Set a registered gauge to `value`
+    #[inline]
+    pub fn set_gauge(&mut self, id: MetricId, value: f64) {
+        debug_assert_eq!(self.inner.metric_descs[id.0].kind, MetricKind::Gauge);
+        self.inner.metrics[id.0].store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Record the average rdtsc for each op
+    pub fn record_avg_rdtsc(&mut self, num: i64) {
+        let cnt_num = self.inner.rdtsc_cnt.load(Ordering::Relaxed) + 1;
+        let value = self.inner.rdtsc_value.load(Ordering::Relaxed);
+        self.inner.rdtsc_cnt.store(cnt_num, Ordering::Relaxed);
+        self.inner.rdtsc_value.store(
+            value.wrapping_add(((num - value as i64) / cnt_num) as u64),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Take a snapshot of the stats recorded so far
+    pub fn snapshot(&self) -> BenchStat {
+        self.inner.snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Metric, MetricDesc, MetricId, MetricKind};
+    use super::BenchStatWriter;
+
+    #[test]
+    fn test_writer_snapshot() {
+        let (mut writer, shared) = BenchStatWriter::new(Default::default(), None);
+        writer.finished_one_op();
+        writer.finished_batch_ops(9);
+        writer.transferred_bytes(640);
+        writer.record_latency_ns(100);
+        writer.record_latency_ns(300);
+        writer.record_batch_latency_ns(200, 8);
+
+        let stat = shared.snapshot();
+        assert_eq!(stat.num_ops_finished, 10);
+        assert_eq!(stat.num_bytes, 640);
+        assert_eq!(stat.latency.count(), 10);
+        assert_eq!(stat.latency.max(), 300);
+        assert!((stat.latency.mean() - 200.0).abs() < 1e-9);
+        assert_eq!(stat, writer.snapshot());
+    }
+
+    #[test]
+    fn test_writer_metrics() {
+        let descs = vec![
+            MetricDesc {
+                name: "bytes".to_string(),
+                kind: MetricKind::Counter,
+            },
+            MetricDesc {
+                name: "inflight".to_string(),
+                kind: MetricKind::Gauge,
+            },
+        ];
+        let (mut writer, shared) = BenchStatWriter::new(descs.into(), None);
+        writer.add_counter(MetricId(0), 64);
+        writer.add_counter(MetricId(0), 64);
+        writer.set_gauge(MetricId(1), 3.0);
+        writer.set_gauge(MetricId(1), 2.0);
+
+        let stat = shared.snapshot();
+        assert_eq!(stat.metrics["bytes"], Metric::Counter(128));
+        assert_eq!(stat.metrics["inflight"], Metric::Gauge(2.0));
+    }
+
+    #[test]
+    fn test_concurrent_read_write() {
+        let (mut writer, shared) = BenchStatWriter::new(Default::default(), None);
+        let worker = std::thread::spawn(move || {
+            for _ in 0..100_000 {
+                writer.finished_one_op();
+            }
+        });
+
+        let mut last = 0;
+        while !worker.is_finished() {
+            let cur = shared.snapshot().num_ops_finished;
+            assert!(cur >= last);
+            last = cur;
+        }
+        worker.join().unwrap();
+        assert_eq!(shared.snapshot().num_ops_finished, 100_000);
+    }
+}
@@ -1,13 +1,20 @@
 use std::time::Instant;
 
-use super::{BenchReporter, BenchStat, CollectedBenchStat};
+use super::{breakdown_of, check_workers, unix_time_ms, BenchReporter, BenchStat, CollectedBenchStat};
+use crate::BenchPhase;
 
 /// A simple reporter that reports the throughput and latency of workers from this machine.
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct SimpleBenchReporter {
     stats_of_last_period: BenchStat,
     last_record_time: Instant,
+    phase: BenchPhase,
     id: usize,
+    // the ops finished by each worker at the last report, if the report breaks them down
+    worker_ops_of_last_period: Vec<u64>,
+    worker_breakdown: bool,
+    // the max spread of the worker throughputs that is not warned about
+    max_worker_spread: f64,
 }
 
 impl Default for SimpleBenchReporter {
@@ -15,7 +22,11 @@ impl Default for SimpleBenchReporter {
         Self {
             stats_of_last_period: BenchStat::default(),
             last_record_time: Instant::now(),
+            phase: BenchPhase::default(),
             id: 0,
+            worker_ops_of_last_period: Vec::new(),
+            worker_breakdown: false,
+            max_worker_spread: f64::INFINITY,
         }
     }
 }
@@ -29,41 +40,63 @@ impl SimpleBenchReporter {
     /// Create a new simple reporter with a given id
     pub fn new_with_id(id: usize) -> Self {
         Self {
-            stats_of_last_period: BenchStat::default(),
-            last_record_time: Instant::now(),
             id,
+            ..Default::default()
         }
     }
+
+    /// Break each report down to the throughput of each worker (see [`CollectedBenchStat::workers`]),
+    /// and warn if their spread, i.e., `(max - min) / mean`, is larger than `max_spread` (e.g., 0.5).
+    /// Use `f64::INFINITY` to never warn.
+    pub fn with_worker_breakdown(mut self, max_spread: f64) -> Self {
+        self.worker_breakdown = true;
+        self.max_worker_spread = max_spread;
+        self
+    }
 }
 
 impl BenchReporter for SimpleBenchReporter {
+    fn set_phase(&mut self, phase: BenchPhase) {
+        self.phase = phase;
+    }
+
     fn report_collected_stat(
         &mut self,
-        stats: &Vec<std::sync::Arc<BenchStat>>,
+        stats: &[BenchStat],
     ) -> CollectedBenchStat {
         let mut new_stat = BenchStat::default();
         for stat in stats {
-            new_stat.num_ops_finished += stat.num_ops_finished;
+            new_stat.merge(stat);
         }
 
         let now = Instant::now();
-        let gap = new_stat - self.stats_of_last_period;
+        let gap = new_stat.clone() - std::mem::take(&mut self.stats_of_last_period);
 
-        // microseconds passed
-        let duration = now.duration_since(self.last_record_time).as_micros() as f64;
-        // mops
-        let throughput = gap.num_ops_finished as f64 / duration;
-        // microseconds
-        let avg_latency = duration / gap.num_ops_finished as f64;
+        let duration = now.duration_since(self.last_record_time);
 
         self.stats_of_last_period = new_stat;
         self.last_record_time = now;
 
-        CollectedBenchStat {
-            id: self.id,
-            throughput,
-            avg_latency,
-            p99_latency: 0.0,
+        let mut res = CollectedBenchStat::from_stat(gap, duration);
+        res.id = self.id;
+        res.phase = self.phase;
+        res.end_time_ms = unix_time_ms();
+        res.start_time_ms = res.end_time_ms.saturating_sub(duration.as_millis() as u64);
+
+        if self.worker_breakdown {
+            let worker_ops: Vec<u64> = stats.iter().map(|s| s.num_ops_finished).collect();
+            let gaps: Vec<(u64, Option<usize>)> = stats
+                .iter()
+                .enumerate()
+                .map(|(i, s)| {
+                    let last = self.worker_ops_of_last_period.get(i).copied().unwrap_or(0);
+                    (s.num_ops_finished.saturating_sub(last), s.core)
+                })
+                .collect();
+            res.workers = breakdown_of(&gaps, duration.as_micros() as f64);
+            self.worker_ops_of_last_period = worker_ops;
+            check_workers(&res, self.max_worker_spread);
         }
+        res
     }
 }
@@ -0,0 +1,161 @@
+//! Smooth the reports of another reporter, so that the trend of a noisy run is easier to read.
+//!
+//! The rates of each report, i.e., the throughput, the bandwidth and the latencies,
+//! are replaced by their exponentially weighted moving average or their moving average over a window (see [`Smoothing`]).
+//! The counts (ops, bytes, latency samples and metrics) are left untouched,
+//! and the smoothing restarts at each phase, so that the warmup does not leak into the measured window.
+use std::collections::VecDeque;
+
+use super::{BenchReporter, BenchStat, CollectedBenchStat};
+use crate::BenchPhase;
+
+/// The number of the smoothed fields of a report
+const NUM_RATES: usize = 8;
+
+/// How to smooth the reports
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Smoothing {
+    /// The exponentially weighted moving average with a weight (in `(0, 1]`) of the latest report
+    Ewma(f64),
+    /// The average of the latest reports, up to the given number
+    Window(usize),
+}
+
+/// A reporter that smooths the reports of the inner reporter, see the module doc
+pub struct SmoothingReporter<R: BenchReporter> {
+    inner: R,
+    smoothing: Smoothing,
+    // the rates of the latest reports in the window
+    window: VecDeque<[f64; NUM_RATES]>,
+    // the moving average so far
+    ewma: Option<[f64; NUM_RATES]>,
+    phase: BenchPhase,
+}
+
+impl<R> SmoothingReporter<R>
+where
+    R: BenchReporter,
+{
+    /// Create a reporter smoothing the reports of `reporter`
+    pub fn new(smoothing: Smoothing, reporter: R) -> Self {
+        match smoothing {
+            Smoothing::Ewma(alpha) => assert!(
+                alpha > 0.0 && alpha <= 1.0,
+                "the weight of EWMA should be in (0, 1]"
+            ),
+            Smoothing::Window(n) => assert!(n > 0, "the window should not be empty"),
+        }
+        Self {
+            inner: reporter,
+            smoothing,
+            window: VecDeque::new(),
+            ewma: None,
+            phase: BenchPhase::default(),
+        }
+    }
+
+    fn smooth(&mut self, rates: [f64; NUM_RATES]) -> [f64; NUM_RATES] {
+        match self.smoothing {
+            Smoothing::Ewma(alpha) => {
+                let mut res = rates;
+                if let Some(prev) = self.ewma {
+                    for (r, p) in res.iter_mut().zip(prev) {
+                        *r = alpha * *r + (1.0 - alpha) * p;
+                    }
+                }
+                self.ewma = Some(res);
+                res
+            }
+            Smoothing::Window(n) => {
+                if self.window.len() == n {
+                    self.window.pop_front();
+                }
+                self.window.push_back(rates);
+                let mut res = [0.0; NUM_RATES];
+                for rates in self.window.iter() {
+                    for (r, v) in res.iter_mut().zip(rates) {
+                        *r += v;
+                    }
+                }
+                res.map(|r| r / self.window.len() as f64)
+            }
+        }
+    }
+}
+
+fn rates_of(stat: &CollectedBenchStat) -> [f64; NUM_RATES] {
+    [
+        stat.throughput,
+        stat.bandwidth,
+        stat.avg_latency,
+        stat.p50_latency,
+        stat.p90_latency,
+        stat.p99_latency,
+        stat.p999_latency,
+        stat.max_latency,
+    ]
+}
+
+fn set_rates(stat: &mut CollectedBenchStat, rates: [f64; NUM_RATES]) {
+    [
+        stat.throughput,
+        stat.bandwidth,
+        stat.avg_latency,
+        stat.p50_latency,
+        stat.p90_latency,
+        stat.p99_latency,
+        stat.p999_latency,
+        stat.max_latency,
+    ] = rates;
+}
+
+impl<R> BenchReporter for SmoothingReporter<R>
+where
+    R: BenchReporter,
+{
+    fn set_phase(&mut self, phase: BenchPhase) {
+        if phase != self.phase {
+            self.window.clear();
+            self.ewma = None;
+            self.phase = phase;
+        }
+        self.inner.set_phase(phase);
+    }
+
+    fn report_collected_stat(&mut self, stats: &[BenchStat]) -> CollectedBenchStat {
+        let mut res = self.inner.report_collected_stat(stats);
+        let rates = self.smooth(rates_of(&res));
+        set_rates(&mut res, rates);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rates_of, Smoothing, SmoothingReporter};
+    use crate::reporter::Replay;
+    use crate::{BenchPhase, BenchReporter};
+
+    #[test]
+    fn test_smoothing() {
+        let mut ewma = SmoothingReporter::new(Smoothing::Ewma(0.5), Replay(vec![4.0, 2.0, 8.0]));
+        let throughputs: Vec<f64> = (0..3)
+            .map(|_| ewma.report_collected_stat(&[]).throughput)
+            .collect();
+        assert_eq!(throughputs, vec![4.0, 3.0, 5.5]);
+
+        let mut window =
+            SmoothingReporter::new(Smoothing::Window(2), Replay(vec![4.0, 2.0, 8.0, 1.0, 7.0]));
+        let throughputs: Vec<f64> = (0..3)
+            .map(|_| window.report_collected_stat(&[]).throughput)
+            .collect();
+        assert_eq!(throughputs, vec![4.0, 3.0, 5.0]);
+        // the counts are kept
+        assert_eq!(window.report_collected_stat(&[]).num_ops, 1);
+
+        // a new phase restarts the smoothing
+        window.set_phase(BenchPhase::Cooldown);
+        let res = window.report_collected_stat(&[]);
+        assert_eq!(rates_of(&res)[0], 7.0);
+    }
+}
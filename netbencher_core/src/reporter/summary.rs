@@ -0,0 +1,190 @@
+//! The summary of a whole run, see [`BenchSummary`].
+//!
+//! The summary is built from the reports of the measured window:
+//! the per-interval throughputs give the spread of the throughput,
+//! while the merged stat of the window gives the totals and the overall latency percentiles.
+use std::time::Duration;
+
+use serde_derive::{Deserialize, Serialize};
+
+use super::CollectedBenchStat;
+
+/// The statistics of the per-interval throughputs (in Mops/s)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ThroughputSummary {
+    /// The mean throughput
+    pub mean: f64,
+    /// The (population) standard deviation of the throughputs
+    pub stddev: f64,
+    /// The lowest throughput
+    pub min: f64,
+    /// The highest throughput
+    pub max: f64,
+    /// The median throughput
+    pub median: f64,
+}
+
+impl ThroughputSummary {
+    /// Summarize the throughputs of a list of intervals, all zeros if the list is empty
+    pub fn from_samples(samples: &[f64]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let variance = samples.iter().map(|s| (s - mean) * (s - mean)).sum::<f64>() / n;
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let mid = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) {
+            (sorted[mid - 1] + sorted[mid]) / 2.0
+        } else {
+            sorted[mid]
+        };
+
+        Self {
+            mean,
+            stddev: variance.sqrt(),
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            median,
+        }
+    }
+}
+
+/// The summary of the measured window of a run.
+///
+/// All the latencies are in microseconds, the throughputs are in Mops/s and the bandwidth is in Gbps.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BenchSummary {
+    /// The number of reported intervals
+    pub num_intervals: usize,
+    /// The statistics of the per-interval throughputs
+    pub throughput: ThroughputSummary,
+    /// The overall throughput of the window
+    pub overall_throughput: f64,
+    /// The overall bandwidth of the window
+    pub bandwidth: f64,
+    /// The average latency of all ops
+    pub avg_latency: f64,
+    /// The 50th latency of all ops
+    pub p50_latency: f64,
+    /// The 90th latency of all ops
+    pub p90_latency: f64,
+    /// The 99th latency of all ops
+    pub p99_latency: f64,
+    /// The 99.9th latency of all ops
+    pub p999_latency: f64,
+    /// The max latency of all ops
+    pub max_latency: f64,
+    /// The number of ops finished in the window
+    pub num_ops: u64,
+    /// The number of bytes transferred in the window
+    pub num_bytes: u64,
+    /// The length of the window
+    pub duration: Duration,
+}
+
+impl BenchSummary {
+    /// Build the summary from the throughputs of each interval, and the merged stat of the whole window.
+    ///
+    /// If no interval is reported, the overall throughput is taken as the only interval.
+    pub fn new(interval_throughputs: &[f64], total: &CollectedBenchStat) -> Self {
+        // microseconds passed
+        let duration = total.duration.as_micros() as f64;
+        let (overall_throughput, bandwidth) = if duration > 0.0 {
+            (
+                total.num_ops as f64 / duration,
+                (total.num_bytes * 8) as f64 / duration / 1000.0,
+            )
+        } else {
+            (0.0, 0.0)
+        };
+        let throughput = if interval_throughputs.is_empty() && duration > 0.0 {
+            ThroughputSummary::from_samples(&[overall_throughput])
+        } else {
+            ThroughputSummary::from_samples(interval_throughputs)
+        };
+
+        Self {
+            num_intervals: interval_throughputs.len(),
+            throughput,
+            overall_throughput,
+            bandwidth,
+            avg_latency: total.avg_latency,
+            p50_latency: total.p50_latency,
+            p90_latency: total.p90_latency,
+            p99_latency: total.p99_latency,
+            p999_latency: total.p999_latency,
+            max_latency: total.max_latency,
+            num_ops: total.num_ops,
+            num_bytes: total.num_bytes,
+            duration: total.duration,
+        }
+    }
+}
+
+impl std::fmt::Display for BenchSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ops, {} bytes in {:.2} s ({} intervals), Throughput: {:.4} Mops/s (mean {:.4}, stddev {:.4}, min {:.4}, median {:.4}, max {:.4}), Bandwidth: {:.4} Gbps, Avg Latency: {:.2} µs, p50: {:.2} µs, p90: {:.2} µs, p99: {:.2} µs, p99.9: {:.2} µs, max: {:.2} µs",
+            self.num_ops,
+            self.num_bytes,
+            self.duration.as_secs_f64(),
+            self.num_intervals,
+            self.overall_throughput,
+            self.throughput.mean,
+            self.throughput.stddev,
+            self.throughput.min,
+            self.throughput.median,
+            self.throughput.max,
+            self.bandwidth,
+            self.avg_latency,
+            self.p50_latency,
+            self.p90_latency,
+            self.p99_latency,
+            self.p999_latency,
+            self.max_latency
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{BenchSummary, ThroughputSummary};
+    use crate::CollectedBenchStat;
+
+    #[test]
+    fn test_throughput_summary() {
+        let s = ThroughputSummary::from_samples(&[4.0, 1.0, 3.0, 2.0]);
+        assert_eq!(s.mean, 2.5);
+        assert_eq!(s.min, 1.0);
+        assert_eq!(s.max, 4.0);
+        assert_eq!(s.median, 2.5);
+        assert!((s.stddev - 1.25f64.sqrt()).abs() < 1e-12);
+
+        assert_eq!(ThroughputSummary::from_samples(&[3.0, 1.0, 2.0]).median, 2.0);
+        assert_eq!(ThroughputSummary::from_samples(&[]), ThroughputSummary::default());
+    }
+
+    #[test]
+    fn test_summary_serde() {
+        let total = CollectedBenchStat {
+            num_ops: 2_000_000,
+            num_bytes: 2_000_000 * 64,
+            duration: Duration::from_secs(2),
+            ..Default::default()
+        };
+        let summary = BenchSummary::new(&[0.9, 1.1], &total);
+        assert!((summary.overall_throughput - 1.0).abs() < 1e-12);
+        assert!((summary.bandwidth - 0.512).abs() < 1e-12);
+
+        let encoded = serde_json::to_string(&summary).unwrap();
+        let decoded: BenchSummary = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(summary, decoded);
+    }
+}
@@ -0,0 +1,106 @@
+//! Fan out the reports to several reporters, e.g., one logging to stdout and one writing to a file.
+//!
+//! Each reporter of a [`TeeReporter`] collects the stats on its own,
+//! and the report of the first one is returned.
+//!
+//! Example usage:
+//!
+//! ```no_run
+//! use netbencher_core::{BenchRunner, FileReporter, SimpleBenchReporter, TeeReporter};
+//!
+//! let runner = BenchRunner::new(1);
+//! runner.run(|_, runner, mut stat, _| {
+//!     while runner.running() {
+//!         stat.finished_one_op();
+//!     }
+//! }, ());
+//!
+//! let mut reporter = TeeReporter::new()
+//!     .with(SimpleBenchReporter::new())
+//!     .with(FileReporter::new("results.jsonl", SimpleBenchReporter::new()).unwrap());
+//! for _ in 0..10 {
+//!     std::thread::sleep(std::time::Duration::from_secs(1));
+//!     println!("{}", runner.report(&mut reporter));
+//! }
+//! runner.stop().unwrap();
+//! ```
+use super::{BenchReporter, BenchStat, CollectedBenchStat};
+use crate::BenchPhase;
+
+/// A reporter that passes the stats to several reporters, and returns the report of the first one
+#[derive(Default)]
+pub struct TeeReporter {
+    reporters: Vec<Box<dyn BenchReporter>>,
+}
+
+impl TeeReporter {
+    /// Create a tee reporter without any reporter
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a reporter, in a builder style
+    pub fn with<R: BenchReporter + 'static>(mut self, reporter: R) -> Self {
+        self.add(reporter);
+        self
+    }
+
+    /// Add a reporter
+    pub fn add<R: BenchReporter + 'static>(&mut self, reporter: R) {
+        self.reporters.push(Box::new(reporter));
+    }
+
+    /// The number of reporters
+    pub fn len(&self) -> usize {
+        self.reporters.len()
+    }
+
+    /// Whether there is no reporter
+    pub fn is_empty(&self) -> bool {
+        self.reporters.is_empty()
+    }
+}
+
+impl BenchReporter for TeeReporter {
+    fn set_phase(&mut self, phase: BenchPhase) {
+        for reporter in self.reporters.iter_mut() {
+            reporter.set_phase(phase);
+        }
+    }
+
+    /// Report to all the reporters, return the report of the first one (or an empty report if there is none)
+    fn report_collected_stat(&mut self, stats: &[BenchStat]) -> CollectedBenchStat {
+        let mut reports = self
+            .reporters
+            .iter_mut()
+            .map(|reporter| reporter.report_collected_stat(stats));
+        let res = reports.next().unwrap_or_default();
+        // the other reporters are run for their side effects
+        reports.for_each(drop);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TeeReporter;
+    use crate::{BenchPhase, BenchReporter, BenchStat, SimpleBenchReporter};
+
+    #[test]
+    fn test_tee_reporter() {
+        let mut reporter = TeeReporter::new()
+            .with(SimpleBenchReporter::new_with_id(1))
+            .with(SimpleBenchReporter::new_with_id(2));
+        assert_eq!(reporter.len(), 2);
+        reporter.set_phase(BenchPhase::Warmup);
+
+        let mut stat = BenchStat::default();
+        stat.finished_batch_ops(10);
+        let res = reporter.report_collected_stat(&[stat]);
+        assert_eq!(res.id, 1);
+        assert_eq!(res.num_ops, 10);
+        assert_eq!(res.phase, BenchPhase::Warmup);
+
+        assert_eq!(TeeReporter::new().report_collected_stat(&[]).num_ops, 0);
+    }
+}
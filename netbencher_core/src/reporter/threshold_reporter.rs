@@ -0,0 +1,128 @@
+//! Flag the intervals whose throughput deviates from the running mean, e.g., a stall or a noisy neighbor.
+//!
+//! The running mean is taken over the reports of the current phase, and a report is flagged if
+//! its throughput differs from the mean of the reports before it by more than the given ratio.
+//! The flagged reports are left out of the mean, so that a stall does not drag down the baseline of the next reports
+//! (a lasting change of the throughput keeps being flagged until the next phase).
+//! The flagged intervals are logged as warnings and counted (see [`ThresholdReporter::num_flagged`]).
+use log::warn;
+
+use super::{BenchReporter, BenchStat, CollectedBenchStat};
+use crate::BenchPhase;
+
+/// The number of reports of a phase before the running mean is trusted
+const MIN_SAMPLES: usize = 3;
+
+/// A reporter that flags the reports of the inner reporter deviating from the running mean, see the module doc
+pub struct ThresholdReporter<R: BenchReporter> {
+    inner: R,
+    // the max deviation (as a ratio of the running mean) that is not flagged
+    max_deviation: f64,
+    // the running mean of the throughputs in the phase (without the flagged reports), and the number of reports in it
+    mean: f64,
+    num_samples: usize,
+    num_flagged: usize,
+    last_flagged: bool,
+    phase: BenchPhase,
+}
+
+impl<R> ThresholdReporter<R>
+where
+    R: BenchReporter,
+{
+    /// Create a reporter flagging the reports of `reporter` whose throughput deviates from the running mean by more than `max_deviation`,
+    /// e.g., 0.1 for 10%
+    pub fn new(max_deviation: f64, reporter: R) -> Self {
+        Self {
+            inner: reporter,
+            max_deviation,
+            mean: 0.0,
+            num_samples: 0,
+            num_flagged: 0,
+            last_flagged: false,
+            phase: BenchPhase::default(),
+        }
+    }
+
+    /// The number of flagged reports so far
+    pub fn num_flagged(&self) -> usize {
+        self.num_flagged
+    }
+
+    /// Whether the latest report is flagged
+    pub fn last_flagged(&self) -> bool {
+        self.last_flagged
+    }
+
+    /// Check a report against the running mean, then add it to the mean unless it is flagged
+    fn check(&mut self, stat: &CollectedBenchStat) {
+        let deviation = if self.mean > 0.0 {
+            (stat.throughput - self.mean) / self.mean
+        } else {
+            0.0
+        };
+        self.last_flagged = self.num_samples >= MIN_SAMPLES && deviation.abs() > self.max_deviation;
+        if self.last_flagged {
+            self.num_flagged += 1;
+            warn!(
+                "{} deviates by {:+.1}% from the running mean {:.4} Mops/s",
+                stat,
+                deviation * 100.0,
+                self.mean
+            );
+            return;
+        }
+        self.num_samples += 1;
+        self.mean += (stat.throughput - self.mean) / self.num_samples as f64;
+    }
+}
+
+impl<R> BenchReporter for ThresholdReporter<R>
+where
+    R: BenchReporter,
+{
+    fn set_phase(&mut self, phase: BenchPhase) {
+        if phase != self.phase {
+            self.mean = 0.0;
+            self.num_samples = 0;
+            self.phase = phase;
+        }
+        self.inner.set_phase(phase);
+    }
+
+    fn report_collected_stat(&mut self, stats: &[BenchStat]) -> CollectedBenchStat {
+        let res = self.inner.report_collected_stat(stats);
+        self.check(&res);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ThresholdReporter;
+    use crate::reporter::Replay;
+    use crate::{BenchPhase, BenchReporter};
+
+    #[test]
+    fn test_threshold() {
+        let mut reporter =
+            ThresholdReporter::new(0.1, Replay(vec![1.0, 1.0, 1.0, 1.05, 0.5, 0.95, 9.0]));
+        let flagged: Vec<bool> = (0..6)
+            .map(|_| {
+                reporter.report_collected_stat(&[]);
+                reporter.last_flagged()
+            })
+            .collect();
+        // the first reports only build the mean, then a 5% deviation is tolerated while a drop to a half is flagged
+        assert_eq!(flagged, vec![false, false, false, false, true, false]);
+        assert_eq!(reporter.num_flagged(), 1);
+        // the flagged report is not in the mean
+        assert_eq!(reporter.num_samples, 5);
+        assert!((reporter.mean - 1.0).abs() < 1e-9, "mean: {}", reporter.mean);
+
+        // a new phase restarts the mean
+        reporter.set_phase(BenchPhase::Cooldown);
+        reporter.report_collected_stat(&[]);
+        assert!(!reporter.last_flagged());
+    }
+}
@@ -0,0 +1,299 @@
+//! The protocol between the [`super::CoordinatedReporter`]s and the [`super::CoordinatedReporterMaster`].
+//!
+//! Each reporter numbers its messages ([`ReportMessage`]) with consecutive sequence numbers,
+//! so that the master can count the lost ones.
+//! A message is serialized as JSON and split into fragments that fit in one datagram without IP fragmentation.
+//! Each fragment starts with a fixed header:
+//!
+//! | seq (u64) | fragment index (u16) | fragment count (u16) |
+//!
+//! (little-endian), followed by a slice of the message.
+//! The master reassembles the fragments of each (reporter address, seq),
+//! and gives up a message once a later one of the same reporter is complete.
+//!
+//! A reporter may first register with a [`ReportBody::Join`] message,
+//! which the master answers with a [`MasterMessage`] in the same framing.
+//! The master of a controlled run also sends the START and STOP messages this way.
+use std::collections::{BTreeSet, HashMap};
+use std::net::SocketAddr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use super::CollectedBenchStat;
+
+/// The size of the header of each fragment
+const FRAGMENT_HEADER_SZ: usize = 12;
+
+/// The maximum payload of one fragment, which fits in the usual 1500-byte MTU
+pub(super) const MAX_FRAGMENT_PAYLOAD: usize = 1400;
+
+/// The number of missing sequence numbers remembered per reporter,
+/// a message later than the older ones is taken as a duplicate
+const MAX_TRACKED_GAPS: usize = 1024;
+
+/// A message from a reporter to the master
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(super) struct ReportMessage {
+    /// The sequence number of the message, starting from 0
+    pub seq: u64,
+    /// The id of the reporter
+    pub id: usize,
+    pub body: ReportBody,
+}
+
+/// The content of a [`ReportMessage`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(super) enum ReportBody {
+    /// Register at the master with a requested id, or let the master assign one if it is None
+    Join(Option<usize>),
+    /// Probe the clock of the master, with the time of the reporter (microseconds since the UNIX epoch).
+    /// A ping reuses the seq of the next message, since it is not counted.
+    Ping(u64),
+    /// The report of an interval, boxed since it is much larger than the other messages
+    Report(Box<CollectedBenchStat>),
+    /// The reporter has finished cleanly, and will send no more reports
+    Final,
+}
+
+/// A message from the master to a reporter, i.e., the reply to a [`ReportBody::Join`] or a control message,
+/// sent as a single-fragment message with seq 0
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(super) enum MasterMessage {
+    /// The reporter has joined with the id
+    Welcome(usize),
+    /// The reporter can not join, e.g., its requested id is used by another reporter
+    Rejected(String),
+    /// The reply to a [`ReportBody::Ping`], with the time of the reporter in the ping,
+    /// and the time of the master when it is received (both in microseconds since the UNIX epoch)
+    Pong { reporter_us: u64, master_us: u64 },
+    /// Start the run, broadcast to the joined reporters of a controlled run
+    Start,
+    /// Stop the run, broadcast to the joined reporters of a controlled run
+    Stop,
+    /// The reply to a [`ReportBody::Final`], the master has recorded that the reporter finished
+    Bye,
+}
+
+/// Split an encoded message into datagrams
+pub(super) fn encode_fragments(seq: u64, msg: &[u8]) -> Vec<Vec<u8>> {
+    let chunks: Vec<&[u8]> = if msg.is_empty() {
+        vec![msg]
+    } else {
+        msg.chunks(MAX_FRAGMENT_PAYLOAD).collect()
+    };
+    assert!(chunks.len() <= u16::MAX as usize, "report message is too large");
+
+    let count = chunks.len() as u16;
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut datagram = Vec::with_capacity(FRAGMENT_HEADER_SZ + chunk.len());
+            datagram.extend_from_slice(&seq.to_le_bytes());
+            datagram.extend_from_slice(&(i as u16).to_le_bytes());
+            datagram.extend_from_slice(&count.to_le_bytes());
+            datagram.extend_from_slice(chunk);
+            datagram
+        })
+        .collect()
+}
+
+/// The fragments received so far of a message
+struct PartialMessage {
+    fragments: Vec<Option<Vec<u8>>>,
+    received: usize,
+}
+
+/// Reassemble the messages from the received datagrams
+#[derive(Default)]
+pub(super) struct Reassembler {
+    partial: HashMap<(SocketAddr, u64), PartialMessage>,
+}
+
+impl Reassembler {
+    /// Feed a datagram from `from`, return the message if all its fragments are received.
+    /// Malformed datagrams are dropped.
+    pub fn feed(&mut self, from: SocketAddr, datagram: &[u8]) -> Option<Vec<u8>> {
+        if datagram.len() < FRAGMENT_HEADER_SZ {
+            return None;
+        }
+        let seq = u64::from_le_bytes(datagram[0..8].try_into().unwrap());
+        let index = u16::from_le_bytes(datagram[8..10].try_into().unwrap()) as usize;
+        let count = u16::from_le_bytes(datagram[10..12].try_into().unwrap()) as usize;
+        let payload = &datagram[FRAGMENT_HEADER_SZ..];
+        if index >= count {
+            return None;
+        }
+        if count == 1 {
+            self.drop_older(from, seq);
+            return Some(payload.to_vec());
+        }
+
+        let partial = self
+            .partial
+            .entry((from, seq))
+            .or_insert_with(|| PartialMessage {
+                fragments: vec![None; count],
+                received: 0,
+            });
+        if partial.fragments.len() != count {
+            return None;
+        }
+        if partial.fragments[index].is_none() {
+            partial.fragments[index] = Some(payload.to_vec());
+            partial.received += 1;
+        }
+        if partial.received < count {
+            return None;
+        }
+
+        let partial = self.partial.remove(&(from, seq)).unwrap();
+        self.drop_older(from, seq);
+        Some(partial.fragments.into_iter().flatten().flatten().collect())
+    }
+
+    /// Give up the incomplete messages of `from` that are older than `seq`
+    fn drop_older(&mut self, from: SocketAddr, seq: u64) {
+        self.partial.retain(|(addr, s), _| *addr != from || *s > seq);
+    }
+
+    /// The number of incomplete messages
+    #[cfg(test)]
+    pub fn num_partial(&self) -> usize {
+        self.partial.len()
+    }
+}
+
+/// How a received sequence number is seen by a [`SeqTracker`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum SeqStatus {
+    /// Later than all the received ones
+    New,
+    /// A late message that was counted as lost
+    Late,
+    /// Already received, e.g., a duplicated datagram or a retried message
+    Duplicate,
+}
+
+/// Track the sequence numbers of a reporter to count the lost messages
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(super) struct SeqTracker {
+    /// The next expected sequence number
+    next: u64,
+    /// The sequence numbers before `next` that are not received (yet)
+    missing: BTreeSet<u64>,
+    /// The number of messages received
+    pub received: u64,
+    /// The number of messages not received (yet)
+    pub lost: u64,
+}
+
+impl SeqTracker {
+    /// Start tracking from `seq`, the earlier sequence numbers are not counted as lost
+    pub fn starting_at(seq: u64) -> Self {
+        Self {
+            next: seq,
+            ..Default::default()
+        }
+    }
+
+    /// Record a received sequence number, the duplicates are not counted
+    pub fn record(&mut self, seq: u64) -> SeqStatus {
+        if seq >= self.next {
+            self.lost += seq - self.next;
+            let first_missing = std::cmp::max(self.next, seq.saturating_sub(MAX_TRACKED_GAPS as u64));
+            self.missing.extend(first_missing..seq);
+            while self.missing.len() > MAX_TRACKED_GAPS {
+                self.missing.pop_first();
+            }
+            self.next = seq + 1;
+            self.received += 1;
+            SeqStatus::New
+        } else if self.missing.remove(&seq) {
+            self.lost -= 1;
+            self.received += 1;
+            SeqStatus::Late
+        } else {
+            SeqStatus::Duplicate
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        encode_fragments, Reassembler, SeqStatus, SeqTracker, MAX_FRAGMENT_PAYLOAD, MAX_TRACKED_GAPS,
+    };
+
+    #[test]
+    fn test_fragments_roundtrip() {
+        let from = "127.0.0.1:1000".parse().unwrap();
+        let msg: Vec<u8> = (0..3 * MAX_FRAGMENT_PAYLOAD + 7).map(|i| i as u8).collect();
+        let mut fragments = encode_fragments(5, &msg);
+        assert_eq!(fragments.len(), 4);
+
+        // out of order and duplicated fragments
+        fragments.swap(0, 3);
+        let dup = fragments[1].clone();
+        let mut r = Reassembler::default();
+        assert_eq!(r.feed(from, &fragments[0]), None);
+        assert_eq!(r.feed(from, &dup), None);
+        assert_eq!(r.feed(from, &fragments[1]), None);
+        assert_eq!(r.feed(from, &fragments[2]), None);
+        assert_eq!(r.feed(from, &fragments[3]), Some(msg));
+        assert_eq!(r.num_partial(), 0);
+    }
+
+    #[test]
+    fn test_incomplete_messages_are_dropped() {
+        let from = "127.0.0.1:1000".parse().unwrap();
+        let other = "127.0.0.1:1001".parse().unwrap();
+        let big = vec![1u8; 2 * MAX_FRAGMENT_PAYLOAD];
+        let mut r = Reassembler::default();
+
+        // only the first fragments of seq 0 arrive
+        assert_eq!(r.feed(from, &encode_fragments(0, &big)[0]), None);
+        assert_eq!(r.feed(other, &encode_fragments(0, &big)[0]), None);
+        assert_eq!(r.num_partial(), 2);
+        // the next message of the same reporter completes
+        assert_eq!(r.feed(from, &encode_fragments(1, b"ok")[0]), Some(b"ok".to_vec()));
+        assert_eq!(r.num_partial(), 1);
+
+        assert_eq!(r.feed(from, &[0u8; 3]), None);
+    }
+
+    #[test]
+    fn test_seq_tracker() {
+        let mut t = SeqTracker::default();
+        assert_eq!(t.record(0), SeqStatus::New);
+        assert_eq!(t.record(1), SeqStatus::New);
+        assert_eq!(t.record(4), SeqStatus::New);
+        assert_eq!(t.lost, 2);
+        // 3 arrives late
+        assert_eq!(t.record(3), SeqStatus::Late);
+        assert_eq!(t.lost, 1);
+        assert_eq!(t.received, 4);
+
+        // the duplicates are neither received nor make up for the lost ones
+        assert_eq!(t.record(3), SeqStatus::Duplicate);
+        assert_eq!(t.record(1), SeqStatus::Duplicate);
+        assert_eq!(t.record(4), SeqStatus::Duplicate);
+        assert_eq!(t.lost, 1);
+        assert_eq!(t.received, 4);
+        assert_eq!(t.record(2), SeqStatus::Late);
+        assert_eq!(t.lost, 0);
+
+        let mut t = SeqTracker::starting_at(2);
+        assert_eq!(t.record(2), SeqStatus::New);
+        assert_eq!(t.lost, 0);
+        // the ones before the start are not missing
+        assert_eq!(t.record(0), SeqStatus::Duplicate);
+
+        // only the recent gaps are remembered
+        let mut t = SeqTracker::default();
+        assert_eq!(t.record(10 * MAX_TRACKED_GAPS as u64), SeqStatus::New);
+        assert_eq!(t.lost, 10 * MAX_TRACKED_GAPS as u64);
+        assert_eq!(t.record(0), SeqStatus::Duplicate);
+        assert_eq!(t.record(10 * MAX_TRACKED_GAPS as u64 - 1), SeqStatus::Late);
+    }
+}
@@ -0,0 +1,228 @@
+//! Graceful shutdown on Ctrl-C.
+//!
+//! [`Shutdown::install`] installs the Ctrl-C (SIGINT) handler of the process. Once Ctrl-C is pressed:
+//! - the runners registered by [`crate::BenchRunner::stop_on`] stop their workers,
+//!   so that the `phase_ticker` ends after a final report, and the bench goes on to its summary;
+//! - [`Shutdown::wait`] completes, e.g., to cancel the event loop of a [`crate::CoordinatedReporterMaster`]
+//!   (which broadcasts STOP to the reporters of a controlled run);
+//! - [`Shutdown::sleep`] returns early.
+//!
+//! A second Ctrl-C exits at once.
+//!
+//! ```no_run
+//! use netbencher_core::{BenchRunner, Shutdown, SimpleBenchReporter};
+//! use std::time::Duration;
+//!
+//! let shutdown = Shutdown::install().expect("failed to install the Ctrl-C handler");
+//! let runner = BenchRunner::new(2);
+//! runner.stop_on(&shutdown);
+//! runner.run(|_, runner, mut stat, _| {
+//!     while runner.running() {
+//!         stat.finished_one_op();
+//!     }
+//! }, ());
+//!
+//! let mut reporter = SimpleBenchReporter::new();
+//! for _ in runner.phase_ticker(Duration::from_secs(1)) {
+//!     println!("{}", runner.report(&mut reporter));
+//! }
+//! runner.stop().unwrap();
+//! println!("summary: {}", runner.summary());
+//! ```
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use log::warn;
+use tokio::sync::Notify;
+
+/// The exit code of a process killed by SIGINT
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+// the shutdown of the Ctrl-C handler, which can be installed only once per process
+static INSTALLED: Mutex<Option<Shutdown>> = Mutex::new(None);
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+/// A shutdown signal shared by the runners, reporters and event loops of a bench
+#[derive(Clone)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    requested: AtomicBool,
+    // the callbacks to run on shutdown, also guards the condvar
+    callbacks: Mutex<Vec<Callback>>,
+    cond: Condvar,
+    notify: Notify,
+}
+
+impl Shutdown {
+    /// Create a shutdown signal, which is triggered by [`Shutdown::trigger`] only
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                requested: AtomicBool::new(false),
+                callbacks: Mutex::new(Vec::new()),
+                cond: Condvar::new(),
+                notify: Notify::new(),
+            }),
+        }
+    }
+
+    /// Install the Ctrl-C handler of the process, and return the shutdown signal it triggers.
+    ///
+    /// Installing it again returns the same signal.
+    pub fn install() -> std::io::Result<Self> {
+        let mut installed = INSTALLED.lock().unwrap();
+        if let Some(shutdown) = installed.as_ref() {
+            return Ok(shutdown.clone());
+        }
+        let shutdown = Self::new();
+        let handler = shutdown.clone();
+        ctrlc::set_handler(move || {
+            if handler.requested() {
+                warn!("interrupted again, exit at once");
+                std::process::exit(INTERRUPTED_EXIT_CODE);
+            }
+            warn!("interrupted, shutting down (press Ctrl-C again to exit at once)");
+            handler.trigger();
+        })
+        .map_err(std::io::Error::other)?;
+        *installed = Some(shutdown.clone());
+        Ok(shutdown)
+    }
+
+    /// Trigger the shutdown: run the callbacks and wake up all the waiters.
+    /// Triggering it again does nothing.
+    pub fn trigger(&self) {
+        let callbacks = {
+            let mut callbacks = self.inner.callbacks.lock().unwrap();
+            if self.inner.requested.swap(true, Ordering::SeqCst) {
+                return;
+            }
+            std::mem::take(&mut *callbacks)
+        };
+        for callback in callbacks {
+            callback();
+        }
+        self.inner.cond.notify_all();
+        self.inner.notify.notify_waiters();
+    }
+
+    /// Check if the shutdown has been triggered
+    #[inline]
+    pub fn requested(&self) -> bool {
+        self.inner.requested.load(Ordering::SeqCst)
+    }
+
+    /// Run `callback` on shutdown, or at once if the shutdown has been triggered
+    pub fn on_shutdown<F: FnOnce() + Send + 'static>(&self, callback: F) {
+        {
+            let mut callbacks = self.inner.callbacks.lock().unwrap();
+            if !self.requested() {
+                callbacks.push(Box::new(callback));
+                return;
+            }
+        }
+        callback();
+    }
+
+    /// Wait until the shutdown is triggered
+    pub async fn wait(&self) {
+        loop {
+            // registered before the check, so that a trigger in between is not missed
+            let notified = self.inner.notify.notified();
+            if self.requested() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Sleep for `duration`, or until the shutdown is triggered.
+    /// Return true if the shutdown has been triggered.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        let mut callbacks = self.inner.callbacks.lock().unwrap();
+        while !self.requested() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            callbacks = self.inner.cond.wait_timeout(callbacks, deadline - now).unwrap().0;
+        }
+        true
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use super::Shutdown;
+
+    #[test]
+    fn test_shutdown_callbacks() {
+        let shutdown = Shutdown::new();
+        let count = Arc::new(AtomicUsize::new(0));
+        let c = count.clone();
+        shutdown.on_shutdown(move || {
+            c.fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(!shutdown.requested());
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        shutdown.trigger();
+        shutdown.trigger();
+        assert!(shutdown.requested());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // registered after the shutdown
+        let c = count.clone();
+        shutdown.on_shutdown(move || {
+            c.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_shutdown_wakes_up_the_waiters() {
+        let shutdown = Shutdown::new();
+        assert!(!shutdown.sleep(Duration::from_millis(1)));
+
+        let trigger = shutdown.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            trigger.trigger();
+        });
+        let begin = Instant::now();
+        assert!(shutdown.sleep(Duration::from_secs(60)));
+        assert!(begin.elapsed() < Duration::from_secs(30));
+        handle.join().unwrap();
+
+        // the future completes once triggered
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let shutdown = Shutdown::new();
+            let trigger = shutdown.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                trigger.trigger();
+            });
+            tokio::time::timeout(Duration::from_secs(30), shutdown.wait())
+                .await
+                .expect("the shutdown is missed");
+            // and at once if triggered before
+            shutdown.wait().await;
+        });
+    }
+}